@@ -0,0 +1,231 @@
+/*   Golden fixtures: runs every Rust solver on its iN inputs, compares with oN
+ *
+ *   rustc -O golden.rs && ./golden [puzzle.rs ...]
+ *   rustc --test golden.rs && ./golden          (same, as a test)
+ *   cargo test                                  (with the puzzles' own tests)
+ *
+ *   Puzzle directories are the *.rs ones holding a main.rs; each iN with an oN
+ *   next to it is a case. GOLDEN_ROOT is the repository (default: current
+ *   directory), RUSTC the compiler, GOLDEN_TIMEOUT seconds per case (10). */
+use std::cmp;
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+
+/* ------------------------------------------------------------- Puzzles */
+// Puzzles whose answer is a set of lines in any order (mine positions)
+const UNORDERED: &[&str] = &["minesweeper.rs"];
+
+// as in Cargo.toml, rustc alone defaults to 2015
+const EDITION: &str = "2021";
+
+struct Puzzle {
+    name: String,
+    dir: PathBuf,
+    cases: Vec<String>,  // "1", "01", ...: iN and oN
+}
+
+impl Puzzle {
+    fn discover(root: &Path) -> Vec<Puzzle> {
+        let mut puzzles: Vec<Puzzle> = Vec::new();
+        for entry in fs::read_dir(root).expect("GOLDEN_ROOT not readable") {
+            let dir = entry.unwrap().path();
+            let name = dir.file_name().unwrap().to_string_lossy().into_owned();
+            if ! name.ends_with(".rs") || ! dir.join("main.rs").is_file() {
+                continue;
+            }
+            let mut cases: Vec<String> = fs::read_dir(&dir).unwrap()
+                .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+                .filter(|f| f.len() > 1 && f.starts_with('i') && f[1..].chars().all(|c| c.is_ascii_digit()))
+                .map(|f| f[1..].to_string())
+                .filter(|n| dir.join(format!("o{}", n)).is_file())
+                .collect();
+            cases.sort_by_key(|n| (n.parse::<u32>().unwrap_or(0), n.clone()));
+            puzzles.push(Puzzle { name, dir, cases });
+        }
+        puzzles.sort_by(|a, b| a.name.cmp(&b.name));
+        puzzles
+    }
+
+    fn unordered(&self) -> bool {
+        UNORDERED.contains(&self.name.as_str())
+    }
+
+    // compiles main.rs into out/, returns the binary
+    fn build(&self, out: &Path) -> Result<PathBuf, String> {
+        let binary = out.join(self.name.trim_end_matches(".rs"));
+        let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+        let output = Command::new(rustc).arg("--edition").arg(EDITION).arg("-O").arg("-o").arg(&binary).arg(self.dir.join("main.rs"))
+            .output().map_err(|e| format!("rustc: {}", e))?;
+        if ! output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+        }
+        Ok(binary)
+    }
+}
+
+
+/* ------------------------------------------------------------- Running */
+struct Outcome {
+    case: String,
+    time: Duration,
+    diff: Option<String>,  // None: passed
+}
+
+// runs binary < input, kills it after timeout; returns stdout
+fn run(binary: &Path, input: &Path, timeout: Duration) -> Result<(String, Duration), String> {
+    let start = Instant::now();
+    let mut child = Command::new(binary)
+        .env("CG_LOG", "quiet")
+        .stdin(fs::File::open(input).map_err(|e| e.to_string())?)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn().map_err(|e| e.to_string())?;
+    // read concurrently, a full pipe would block the child
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = thread::spawn(move || {
+        let mut text = String::new();
+        stdout.read_to_string(&mut text).map(|_| text)
+    });
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+            break status;
+        }
+        if start.elapsed() > timeout {
+            child.kill().ok();
+            child.wait().ok();
+            return Err(format!("timeout after {:?}", timeout));
+        }
+        thread::sleep(Duration::from_millis(2));
+    };
+    let time = start.elapsed();
+    let text = reader.join().unwrap().map_err(|e| e.to_string())?;
+    if ! status.success() {
+        let mut stderr = String::new();
+        child.stderr.take().unwrap().read_to_string(&mut stderr).ok();
+        return Err(format!("{}\n{}", status, stderr.trim_end()));
+    }
+    Ok((text, time))
+}
+
+// lines without trailing spaces or blank lines, sorted if order does not matter
+fn normalize(text: &str, unordered: bool) -> Vec<String> {
+    let mut lines: Vec<String> = text.lines()
+        .map(|l| l.trim_end().to_string())
+        .filter(|l| ! l.is_empty())
+        .collect();
+    if unordered {
+        lines.sort();
+    }
+    lines
+}
+
+// None if equal, else the differing lines as "-expected" / "+got"
+fn compare(expected: &str, got: &str, unordered: bool) -> Option<String> {
+    let (expected, got) = (normalize(expected, unordered), normalize(got, unordered));
+    if expected == got {
+        return None;
+    }
+    let mut diff = String::new();
+    let mut shown = 0;
+    for i in 0..cmp::max(expected.len(), got.len()) {
+        let (e, g) = (expected.get(i), got.get(i));
+        if e == g {
+            continue;
+        }
+        if shown == 10 {
+            diff.push_str("...\n");
+            break;
+        }
+        diff.push_str(&format!("line {}:\n", i + 1));
+        if let Some(e) = e { diff.push_str(&format!("-{}\n", e)); }
+        if let Some(g) = g { diff.push_str(&format!("+{}\n", g)); }
+        shown += 1;
+    }
+    Some(diff)
+}
+
+fn check_puzzle(puzzle: &Puzzle, out: &Path, timeout: Duration) -> Result<Vec<Outcome>, String> {
+    let binary = puzzle.build(out)?;
+    let mut outcomes: Vec<Outcome> = Vec::new();
+    for case in &puzzle.cases {
+        let input = puzzle.dir.join(format!("i{}", case));
+        let expected = fs::read_to_string(puzzle.dir.join(format!("o{}", case))).map_err(|e| e.to_string())?;
+        let outcome = match run(&binary, &input, timeout) {
+            Ok((got, time)) => Outcome { case: case.clone(), time, diff: compare(&expected, &got, puzzle.unordered()) },
+            Err(e) => Outcome { case: case.clone(), time: Duration::new(0, 0), diff: Some(e) },
+        };
+        outcomes.push(outcome);
+    }
+    Ok(outcomes)
+}
+
+// checks all (or the named) puzzles, reports to w; returns the number of failures
+fn check_all(only: &[String], w: &mut dyn Write) -> usize {
+    let root = PathBuf::from(env::var("GOLDEN_ROOT").unwrap_or_else(|_| ".".to_string()));
+    let timeout = Duration::from_secs(env::var("GOLDEN_TIMEOUT").ok().and_then(|t| t.parse().ok()).unwrap_or(10));
+    let out = env::temp_dir().join(format!("golden-{}", std::process::id()));
+    fs::create_dir_all(&out).expect("no temporary directory");
+
+    let mut failures = 0;
+    for puzzle in Puzzle::discover(&root) {
+        if ! only.is_empty() && ! only.contains(&puzzle.name) {
+            continue;
+        }
+        match check_puzzle(&puzzle, &out, timeout) {
+            Err(e) => {
+                writeln!(w, "FAIL {} (build)\n{}", puzzle.name, e).unwrap();
+                failures += 1;
+            },
+            Ok(outcomes) => for outcome in outcomes {
+                let time = outcome.time.as_secs() as f64 * 1000.0 + outcome.time.subsec_nanos() as f64 / 1e6;
+                match outcome.diff {
+                    None => writeln!(w, "ok   {}/i{} {:.1}ms", puzzle.name, outcome.case, time).unwrap(),
+                    Some(diff) => {
+                        writeln!(w, "FAIL {}/i{} {:.1}ms\n{}", puzzle.name, outcome.case, time, diff.trim_end()).unwrap();
+                        failures += 1;
+                    },
+                }
+            },
+        }
+    }
+    fs::remove_dir_all(&out).ok();
+    failures
+}
+
+
+/* ------------------------------------------------------------- main */
+#[cfg(not(test))]
+fn main() {
+    let only: Vec<String> = env::args().skip(1).collect();
+    let failures = check_all(&only, &mut std::io::stdout());
+    if failures > 0 {
+        eprintln!("{} golden case(s) failed", failures);
+        std::process::exit(1);
+    }
+}
+
+
+/* ------------------------------------------------------------- tests */
+#[test]
+fn golden_fixtures() {
+    let mut report: Vec<u8> = Vec::new();
+    let failures = check_all(&[], &mut report);
+    let report = String::from_utf8(report).unwrap();
+    println!("{}", report);
+    assert!(report.contains("ok   "), "no fixtures found, set GOLDEN_ROOT");
+    assert_eq!(failures, 0, "\n{}", report);
+}
+
+#[test]
+fn golden_compare() {
+    assert_eq!(compare("1\n2\n", "1\n2", false), None);
+    assert_eq!(compare("1 2\n\n3 4\n", "3 4  \n1 2\n", true), None);
+    assert_eq!(compare("1 2\n3 4\n", "3 4\n1 2\n", false), Some("line 1:\n-1 2\n+3 4\nline 2:\n-3 4\n+1 2\n".to_string()));
+    assert_eq!(compare("5\n", "", false), Some("line 1:\n-5\n".to_string()));
+}
@@ -1,9 +1,16 @@
 /*   https://www.codingame.com/ide/puzzle/minesweeper
  *   Krzysztof Garus, 2018                            */
+#![cfg_attr(test, allow(dead_code))]  // main() and what only it uses
 use std::io;
+use std::io::{BufRead, Read, Write};
 use std::vec::Vec;
 use std::cmp;
+use std::env;
 use std::fmt;
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 
 
 /* ------------------------------------------------------------- CG macros */
@@ -11,6 +18,16 @@ macro_rules! parse_input {
     ($x:expr, $t:ident) => ($x.trim().parse::<$t>().unwrap())
 }
 
+// log!(LogLevel::Delta, "event", key = value, ...), see Logging
+macro_rules! log {
+    ($level:expr, $event:expr $(, $key:ident = $value:expr)*) => (
+        if log_enabled($level) {
+            let fields: Vec<(&str, String)> = vec![$((stringify!($key), format!("{}", $value))),*];
+            eprintln!("{}", log_line($event, &fields, LOG_EVENTS.load(Ordering::Relaxed)));
+        }
+    )
+}
+
 
 /* ------------------------------------------------------------- Data
     .-----------------+-------+---------------.
@@ -30,15 +47,24 @@ macro_rules! parse_input {
 struct Board {
     w: u32,
     h: u32,
-    bombs: u32,
+    d: u32,  // layers, 1 but for 3D boards
+    bombs: u32,      // total, the lowest one when only a range is known
+    bombs_max: u32,  // highest total: bombs, or u32::MAX when unknown, see parse_total()
     squares: Vec<Square>,
     hints: Vec<Hint>,
     stack: Vec<usize>,  // squares to investigate
+    digits: Vec<Option<i32>>,  // revealed digit of each square, '.' is Some(0)
+    clues: Vec<String>,        // variant clues given with the grid, see add_clues()
+    range: (i32, i32),         // mines a square may hold, (0,1) but for variants
+    history: History,
+    stats: Stats,
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq,Clone)]
 struct Hint {
-    bombs: u32,
+    bombs: i32,  // sum of the squares values
+    alt: Option<i32>,     // liar clues: the sum is bombs OR alt, see process_search()
+    lie: Option<usize>,   // "liar one" clues: group where exactly one hint is wrong, see process_search()
     squares: Vec<usize>,  // sorted
     valid: bool,
 }
@@ -50,29 +76,132 @@ struct Square {
     hints: Vec<usize>,
 }
 
-#[derive(PartialEq,Debug)]
+#[derive(PartialEq,Debug,Clone,Copy)]
 enum SquareState {
     Unknown,
-    Empty,
-    Bomb,
+    Empty,       // holds 0
+    Bomb,        // holds 1
+    Mines(i32),  // variants: any other number of mines, negative ones too
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq,Clone)]
 struct Delta {
     hints2rm: Vec<usize>,
     hints2add: Vec<Hint>,
     squares2bomb: Vec<usize>,
     squares2empty: Vec<usize>,
+    squares2mines: Vec<(usize, i32)>,  // neither bomb nor empty, see set_square_value()
+}
+
+// Everything done to the board since parsing, so it can be walked back and forth.
+// Only kept once recording is on (stepper, SVG steps, undo): solving alone never
+// walks back, and a record of every pop costs memory on big boards.
+struct History {
+    done: Vec<(Step, Undo)>,
+    undone: Vec<Step>,  // for redo, next one at the end
+    recording: bool,
+}
+
+enum Step {
+    Pop(usize),    // square taken from the stack
+    Push(usize),   // square put back on the stack
+    Delta(Delta),  // applied delta
+}
+
+// When to give up solving, see Board::solve()
+#[derive(Default,Clone)]
+struct Limits {
+    steps: Option<u64>,              // squares popped from the stack
+    deadline: Option<Instant>,
+    cancel: Option<Arc<AtomicBool>>, // set from another thread to stop
+    spent: Option<Arc<AtomicU64>>,   // steps of all boards sharing it, see solve_parallel()
+}
+
+#[derive(PartialEq,Debug,Clone,Copy)]
+enum Outcome {
+    Complete,
+    Incomplete(Stop),  // board holds what was found so far, solve() again to go on
+}
+
+#[derive(PartialEq,Debug,Clone,Copy)]
+enum Stop {
+    Steps,
+    Deadline,
+    Cancelled,
+    Nodes,  // a liar search gave up after SEARCH_NODES, solving again won't help
+}
+
+// Work counters, printed at the end of process_stack()
+#[derive(Default)]
+struct Stats {
+    pops: u64,
+    rules: [Cell<u64>; 10],  // firings, by Rule
+    hints_added: u64,
+    hints_removed: u64,
+    hints_alive: usize,
+    hints_peak: usize,
+    times: [Duration; 5],   // by Phase
+}
+
+#[derive(Clone,Copy)]
+enum Rule {
+    Known,      // process_hint: some squares already defined
+    NoBombs,    // process_hint: no bombs
+    AllBombs,   // process_hint: all bombs
+    Duplicate,  // process_hint_pair: same squares
+    Inside,     // process_hint_pair: h1 inside h2
+    Intersect,  // process_hint_pair: bombs in common part known
+    Remaining,  // process_remaining: x bombs on x unknown squares
+    Single,     // process_hint: one square left, it holds the sum
+    Liar,       // process_hint: one count of a liar hint left
+    Search,     // process_search: same value in every solution
+}
+
+#[derive(Clone,Copy)]
+enum Phase {
+    Parse,      // from_rows()
+    Hints,      // process_hint() for each popped square
+    Pairs,      // process_hint_pair() for each popped square
+    Remaining,  // process_remaining()
+    Search,     // process_search()
+}
+
+// what a Step changed, as it was before
+struct Undo {
+    squares: Vec<(usize, SquareState, Vec<usize>, bool)>,  // state, hints, on_stack
+    hints: Vec<(usize, Hint)>,  // removed hints
+    hints_len: usize,
+    stack_len: usize,
 }
 
 /* ------------------------------------------------------------- Impl */
 impl Hint {
-    fn new(bombs: u32, mut squares: Vec<usize>) -> Hint {
+    // bombs are checked against the board range by add_clues()
+    fn new(bombs: i32, mut squares: Vec<usize>) -> Hint {
         squares.sort();
         squares.dedup();
-        assert!(bombs <= squares.len() as u32);
-        let empty = squares.len() == 0;
-        Hint { bombs: bombs, squares: squares, valid: !empty }
+        assert!(!squares.is_empty() || bombs == 0);
+        let empty = squares.is_empty();
+        Hint { bombs, alt: None, lie: None, squares, valid: !empty }
+    }
+
+    // sum is one of two counts, None if no square is left and neither is 0
+    fn either(bombs: i32, alt: i32, squares: Vec<usize>) -> Option<Hint> {
+        if squares.is_empty() {
+            return if bombs == 0 || alt == 0 { Some(Hint::new(0, squares)) } else { None };
+        }
+        let mut hint = Hint::new(cmp::min(bombs, alt), squares);
+        hint.alt = Some(cmp::max(bombs, alt)).filter(|&max| max != hint.bombs);
+        Some(hint)
+    }
+
+    // counts the sum may be, given lo..hi mines a square
+    fn sums(&self, lo: i32, hi: i32) -> Vec<i32> {
+        let n = self.squares.len() as i32;
+        let mut sums = vec![self.bombs];
+        sums.extend(self.alt);
+        sums.retain(|&b| b >= n*lo && b <= n*hi);
+        sums
     }
 
     // return (h1, h2, both) square number sets
@@ -118,9 +247,29 @@ impl Square {
     }
 }
 
+impl SquareState {
+    fn of(value: i32) -> SquareState {
+        match value {
+            0 => SquareState::Empty,
+            1 => SquareState::Bomb,
+            v => SquareState::Mines(v),
+        }
+    }
+
+    // number of mines, None if Unknown
+    fn value(&self) -> Option<i32> {
+        match *self {
+            SquareState::Unknown => None,
+            SquareState::Empty => Some(0),
+            SquareState::Bomb => Some(1),
+            SquareState::Mines(v) => Some(v),
+        }
+    }
+}
+
 impl Delta {
     fn new() -> Delta {
-        Delta { hints2rm: Vec::new(), hints2add: Vec::new(), squares2bomb: Vec::new(), squares2empty: Vec::new(), }
+        Delta { hints2rm: Vec::new(), hints2add: Vec::new(), squares2bomb: Vec::new(), squares2empty: Vec::new(), squares2mines: Vec::new(), }
     }
 
     fn add_hint(&mut self, hint: Hint) {
@@ -136,110 +285,608 @@ impl Delta {
     fn set_square_empty(&mut self, s: usize) {
         self.squares2empty.push(s);
     }
+    fn set_square_value(&mut self, s: usize, value: i32) {
+        match value {
+            0 => self.set_square_empty(s),
+            1 => self.set_square_bomb(s),
+            v => self.squares2mines.push((s, v)),
+        }
+    }
 
     fn reset(&mut self) {
         self.hints2rm.truncate(0);
         self.hints2add.truncate(0);
         self.squares2bomb.truncate(0);
         self.squares2empty.truncate(0);
+        self.squares2mines.truncate(0);
     }
     fn is_empty(&self) -> bool {
         self.hints2rm.is_empty() &&
         self.hints2add.is_empty() &&
         self.squares2bomb.is_empty() &&
-        self.squares2empty.is_empty()
+        self.squares2empty.is_empty() &&
+        self.squares2mines.is_empty()
+    }
+}
+
+impl Limits {
+    // limit reached after given number of steps (or the shared spent ones)
+    fn check(&self, steps: u64) -> Option<Stop> {
+        self.check_at(self.spent.as_ref().map_or(steps, |spent| spent.load(Ordering::Relaxed)))
+    }
+
+    // check() before one more step, counted in spent at once:
+    // boards sharing it never take more than steps together
+    fn take(&self, steps: u64) -> Option<Stop> {
+        match self.spent {
+            None => self.check_at(steps),
+            Some(ref spent) => {
+                let stop = self.check_at(spent.fetch_add(1, Ordering::Relaxed));
+                if stop.is_some() { spent.fetch_sub(1, Ordering::Relaxed); }
+                stop
+            },
+        }
+    }
+
+    fn check_at(&self, steps: u64) -> Option<Stop> {
+        if let Some(ref cancel) = self.cancel {
+            if cancel.load(Ordering::Relaxed) { return Some(Stop::Cancelled); }
+        }
+        if self.steps.is_some_and(|max| steps >= max) {
+            return Some(Stop::Steps);
+        }
+        if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Some(Stop::Deadline);
+        }
+        None
+    }
+}
+
+impl History {
+    fn new() -> History {
+        History { done: Vec::new(), undone: Vec::new(), recording: false }
+    }
+}
+
+const RULE_NAMES: [&str; 10] = ["known", "no_bombs", "all_bombs", "duplicate", "inside", "intersect", "remaining", "single",
+                                "liar", "search"];
+const PHASE_NAMES: [&str; 5] = ["parse", "hints", "pairs", "remaining", "search"];
+
+impl Stats {
+    fn rule(&self, rule: Rule) {
+        let counter = &self.rules[rule as usize];
+        counter.set(counter.get() + 1);
+    }
+
+    fn time(&mut self, phase: Phase, since: Instant) {
+        self.times[phase as usize] += since.elapsed();
+    }
+
+    fn hint_added(&mut self) {
+        self.hints_added += 1;
+        self.hints_alive += 1;
+        self.hints_peak = cmp::max(self.hints_peak, self.hints_alive);
+    }
+
+    // counters of a region board that started with copies of the given number
+    // of hints, see solve_parallel(); the peak as if regions ran one after another
+    fn add(&mut self, other: &Stats, copied: usize) {
+        self.pops += other.pops;
+        self.hints_added += other.hints_added - copied as u64;
+        self.hints_removed += other.hints_removed;
+        self.hints_peak = cmp::max(self.hints_peak, self.hints_alive - other.hints_alive + other.hints_peak);
+        for (rule, count) in self.rules.iter().zip(other.rules.iter()) {
+            rule.set(rule.get() + count.get());
+        }
+        for (time, other) in self.times.iter_mut().zip(other.times.iter()) {
+            *time += *other;
+        }
+    }
+
+    fn hint_removed(&mut self) {
+        self.hints_removed += 1;
+        self.hints_alive -= 1;
+    }
+
+    // every delta comes from exactly one rule
+    fn deltas(&self) -> u64 {
+        self.rules.iter().map(|c| c.get()).sum()
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields: Vec<(&'static str, String)> = vec![("pops", format!("{}", self.pops))];
+        for (name, count) in RULE_NAMES.iter().zip(self.rules.iter()) {
+            fields.push((name, format!("{}", count.get())));
+        }
+        fields.push(("hints_added", format!("{}", self.hints_added)));
+        fields.push(("hints_removed", format!("{}", self.hints_removed)));
+        fields.push(("hints_peak", format!("{}", self.hints_peak)));
+        for (name, time) in PHASE_NAMES.iter().zip(self.times.iter()) {
+            fields.push((name, format!("{}us", time.as_secs()*1_000_000 + time.subsec_micros() as u64)));
+        }
+        fields
+    }
+
+    // block in text logs, a single event otherwise
+    fn log(&self) {
+        if ! log_enabled(LogLevel::Summary) { return; }
+        if LOG_EVENTS.load(Ordering::Relaxed) {
+            eprintln!("{}", log_line("stats", &self.fields(), true));
+        } else {
+            eprintln!("{}", self);
+        }
     }
 }
 
 /* ------------------------------------------------------------- Impl: Board I/O */
 impl Board {
     // make board with Empty Square's
-    fn new(w: u32, h: u32, bombs: u32) -> Board {
-        let mut squares: Vec<Square> = Vec::with_capacity((w*h) as usize);
-        for _ in 0..w*h {
+    fn new(w: u32, h: u32, d: u32, bombs: u32) -> Board {
+        let mut squares: Vec<Square> = Vec::with_capacity((w*h*d) as usize);
+        for _ in 0..w*h*d {
             squares.push( Square::new() );
         }
-        Board { w: w,
-                h: h,
-                bombs: bombs,
-                squares: squares,
+        Board { w,
+                h,
+                d,
+                bombs,
+                bombs_max: bombs,
+                squares,
                 hints: Vec::new(),
                 stack: Vec::new(),
+                digits: vec![None; (w*h*d) as usize],
+                clues: Vec::new(),
+                range: (0, 1),
+                history: History::new(),
+                stats: Stats::default(),
         }
     }
 
     fn from_stdin() -> Board {
+        let stdin = io::stdin();
+        let mut input = stdin.lock();
+        Board::from_reader(&mut input)
+    }
+
+    // CodinGame format: "h w", bombs, h rows of the grid
+    // Variant puzzles give the number of clue lines after bombs: "bombs n",
+    // and the n clues follow the grid, see add_clues()
+    // Unknown total: bombs is '?' or a range "LO-HI", see parse_total()
+    // 3D boards: "h w d", then d layers of h rows one after another, z=0 first
+    fn from_reader<R: BufRead>(input: &mut R) -> Board {
         let mut input_line = String::new();
-        io::stdin().read_line(&mut input_line).unwrap();
-        let inputs = input_line.split(" ").collect::<Vec<_>>();
+        input.read_line(&mut input_line).unwrap();
+        let inputs = input_line.split_whitespace().collect::<Vec<_>>();
         let h = parse_input!(inputs[0], u32);
         let w = parse_input!(inputs[1], u32);
+        let d = if inputs.len() > 2 { parse_input!(inputs[2], u32) } else { 1 };
         
         let mut input_line = String::new();
-        io::stdin().read_line(&mut input_line).unwrap();
-        let nb = parse_input!(input_line, u32);
+        input.read_line(&mut input_line).unwrap();
+        let inputs = input_line.split_whitespace().collect::<Vec<_>>();
+        let (nb, nb_max) = parse_total(inputs[0]).unwrap();
+        let n_clues = if inputs.len() > 1 { parse_input!(inputs[1], usize) } else { 0 };
         
-        let mut board = Board::new(w, h, nb);
-        
-        for y in 0..h {
+        let mut rows: Vec<String> = Vec::with_capacity((h*d) as usize);
+        for _ in 0..h*d {
+            let mut input_line = String::new();
+            input.read_line(&mut input_line).unwrap();
+            rows.push(input_line.trim_end().to_string());
+        }
+
+        let mut clues: Vec<String> = Vec::with_capacity(n_clues);
+        for _ in 0..n_clues {
             let mut input_line = String::new();
-            io::stdin().read_line(&mut input_line).unwrap();
-            let line = input_line.trim_right().to_string();
-            
-            for (ix,ch) in line.chars().enumerate() {
+            input.read_line(&mut input_line).unwrap();
+            clues.push(input_line.trim().to_string());
+        }
+
+        let mut board = Board::from_layers(w, h, d, nb, &rows);
+        board.bombs_max = nb_max;
+        board.add_clues(&clues).unwrap();
+        board
+    }
+
+    // from_reader() for untrusted text: errors instead of panics
+    fn parse_cg(text: &str) -> Result<Board, String> {
+        let mut lines = text.lines();
+        let header = lines.next().unwrap_or("");
+        let hw: Vec<u32> = header.split_whitespace().map(|n| n.parse::<u32>()).collect::<Result<_,_>>()
+            .map_err(|_| format!("bad header '{}'", header))?;
+        if hw.len() != 2 && hw.len() != 3 {
+            return Err(format!("bad header '{}'", header));
+        }
+        let (h, w, d) = (hw[0], hw[1], *hw.get(2).unwrap_or(&1));
+        let bombs_line = lines.next().unwrap_or("");
+        let counts: Vec<&str> = bombs_line.split_whitespace().collect();
+        let bad = || format!("bad bombs count '{}'", bombs_line);
+        let ((bombs, bombs_max), n_clues) = match counts.len() {
+            1 => (parse_total(counts[0]).ok_or(bad())?, 0),
+            2 => (parse_total(counts[0]).ok_or(bad())?, counts[1].parse::<usize>().map_err(|_| bad())?),
+            _ => return Err(bad()),
+        };
+        let rows: Vec<String> = lines.by_ref().take((h*d) as usize).map(|l| l.trim_end().to_string()).collect();
+        check_board_rows(w, h*d, &rows)?;
+        let clues: Vec<String> = lines.take(n_clues).map(|l| l.trim().to_string()).collect();
+        if clues.len() != n_clues {
+            return Err(format!("{} clues expected, got {}", n_clues, clues.len()));
+        }
+        let mut board = Board::from_layers(w, h, d, bombs, &rows);
+        board.bombs_max = bombs_max;
+        board.add_clues(&clues)?;
+        Ok(board)
+    }
+
+    // Grid characters: '.' and '1'..'8' revealed, '?' unknown,
+    // and for saved solved states: '*' known bomb, '-' known empty (not revealed)
+    // Variant rows are space separated tokens, see parse_square(): there
+    // every revealed digit makes a hint, '.' and '0' too.
+    fn from_rows(w: u32, h: u32, bombs: u32, rows: &[String]) -> Board {
+        Board::from_layers(w, h, 1, bombs, rows)
+    }
+
+    // d layers of h rows
+    fn from_layers(w: u32, h: u32, d: u32, bombs: u32, rows: &[String]) -> Board {
+        let start = Instant::now();
+        let mut board = Board::new(w, h, d, bombs);
+
+        for (iy,line) in rows.iter().enumerate() {
+            let (y, z) = (iy as u32 % h, iy as u32 / h);
+            let spaced = line.contains(' ');
+            for (ix,token) in grid_tokens(line).iter().enumerate() {
                 let x = ix as u32;
-                let s = board.xy2s(x, y);
-                match ch {
-                    '.' => { let s = board.xy2s(x,y); board.set_square_state(s, SquareState::Empty); },
-                    '?' => {}
-                    '1' => { let hint = board.bomb2hint(x, y, 1); board.add_hint(hint); board.set_square_state(s, SquareState::Empty); },
-                    '2' => { let hint = board.bomb2hint(x, y, 2); board.add_hint(hint); board.set_square_state(s, SquareState::Empty); },
-                    '3' => { let hint = board.bomb2hint(x, y, 3); board.add_hint(hint); board.set_square_state(s, SquareState::Empty); },
-                    '4' => { let hint = board.bomb2hint(x, y, 4); board.add_hint(hint); board.set_square_state(s, SquareState::Empty); },
-                    '5' => { let hint = board.bomb2hint(x, y, 5); board.add_hint(hint); board.set_square_state(s, SquareState::Empty); },
-                    '6' => { let hint = board.bomb2hint(x, y, 6); board.add_hint(hint); board.set_square_state(s, SquareState::Empty); },
-                    '7' => { let hint = board.bomb2hint(x, y, 7); board.add_hint(hint); board.set_square_state(s, SquareState::Empty); },
-                    '8' => { let hint = board.bomb2hint(x, y, 8); board.add_hint(hint); board.set_square_state(s, SquareState::Empty); },
-                    _ => unreachable!(),
+                let s = board.xyz2s(x, y, z);
+                let (digit, state) = parse_square(token).unwrap();
+                if let Some(digit) = digit {
+                    if digit != 0 || spaced {
+                        let hint = board.bomb2hint(x, y, z, digit);
+                        board.add_hint(hint);
+                    }
+                    board.digits[s] = Some(digit);
+                }
+                if state != SquareState::Unknown {
+                    board.set_square_state(s, state);
                 }
             }
         }
 
+        board.stats.time(Phase::Parse, start);
         board
     }
 
+    // inverse of from_rows(), variant boards get spaced rows
+    fn to_rows(&self) -> Vec<String> {
+        let tokens = self.to_tokens();
+        let spaced = self.range != (0, 1) || tokens.iter().flatten().any(|token| token.len() > 1);  // 3D counts up to 26
+        tokens.iter().map(|row| row.join(if spaced { " " } else { "" })).collect()
+    }
+
+    // rows of all layers, z=0 first
+    fn to_tokens(&self) -> Vec<Vec<String>> {
+        (0..self.h*self.d).map(|r| (0..self.w).map(|x| {
+            let s = self.xyz2s(x, r % self.h, r / self.h);
+            match (self.digits[s], &self.squares[s].state) {
+                (Some(0), _) => ".".to_string(),
+                (Some(d), _) => format!("{}", d),
+                (None, &SquareState::Bomb) => "*".to_string(),
+                (None, &SquareState::Empty) => "-".to_string(),
+                (None, &SquareState::Mines(v)) => format!("={}", v),
+                (None, &SquareState::Unknown) => "?".to_string(),
+            }
+        }).collect()).collect()
+    }
+
+    // CodinGame format, readable by from_reader()
+    fn write_ascii<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        if self.d > 1 {
+            writeln!(out, "{} {} {}", self.h, self.w, self.d)?;
+        } else {
+            writeln!(out, "{} {}", self.h, self.w)?;
+        }
+        if !self.clues.is_empty() {
+            writeln!(out, "{} {}", self.total(), self.clues.len())?;
+        } else {
+            writeln!(out, "{}", self.total())?;
+        }
+        for row in self.to_rows() {
+            writeln!(out, "{}", row)?;
+        }
+        for clue in self.clues.iter() {
+            writeln!(out, "{}", clue)?;
+        }
+        Ok(())
+    }
+
+    // {"width":w, "height":h, "bombs":n, "rows":["..1??", ...], "clues":["row 0 2", ...]}
+    // clues are optional, so is "depth":d for 3D boards (d*h rows)
+    // an unknown total is a string: "bombs":"?" or "bombs":"LO-HI"
+    fn from_json(json: &Json) -> Result<Board, String> {
+        let w = json.get_u32("width")?;
+        let h = json.get_u32("height")?;
+        let d = if json.get("depth").is_some() { json.get_u32("depth")? } else { 1 };
+        let (bombs, bombs_max) = json.get_total("bombs")?;
+        let rows = json.get_strings("rows")?;
+        check_board_rows(w, h*d, &rows)?;
+        let mut board = Board::from_layers(w, h, d, bombs, &rows);
+        board.bombs_max = bombs_max;
+        if json.get("clues").is_some() {
+            board.add_clues(&json.get_strings("clues")?)?;
+        }
+        Ok(board)
+    }
+
+    fn to_json(&self) -> Json {
+        let mut pairs = vec![
+            ("width".to_string(), Json::Int(self.w as i64)),
+            ("height".to_string(), Json::Int(self.h as i64)),
+            ("bombs".to_string(), self.total_json()),
+            ("rows".to_string(), Json::Arr(self.to_rows().into_iter().map(Json::Str).collect())),
+        ];
+        if self.d > 1 {
+            pairs.insert(2, ("depth".to_string(), Json::Int(self.d as i64)));
+        }
+        if !self.clues.is_empty() {
+            pairs.push(("clues".to_string(), Json::Arr(self.clues.iter().cloned().map(Json::Str).collect())));
+        }
+        Json::Obj(pairs)
+    }
+
+    // Variant clues, each one a Hint over its squares:
+    //   row Y N                 N bombs in row Y
+    //   col X N                 N bombs in column X
+    //   region N x,y x,y ...    N bombs among listed squares, x,y,z on 3D boards
+    //                           (row and col on flat boards only)
+    //   mines LO HI             each square holds LO..HI mines, 0..1 by default
+    //                           (multi-mine 0 2, negative mines -1 1, 64 values at most)
+    //   liar                    every digit of the grid is off by one
+    //   liar row|col|region ..  that clue is off by one
+    //   liar one x,y x,y ...    exactly one of the digits on listed squares is wrong,
+    //                           by any amount (not with liar)
+    fn add_clues(&mut self, clues: &[String]) -> Result<(), String> {
+        let digit_hints = self.hints.len();  // from_rows() made them
+        let mut lies = 0;
+        for clue in clues.iter() {
+            match parse_range(clue) {
+                Some(range) => self.range = range?,
+                None if clue.trim() == "liar" => {
+                    if lies > 0 {
+                        return Err("liar: not with liar one".to_string());
+                    }
+                    for hint in self.hints[..digit_hints].iter_mut() {
+                        *hint = Hint::either(hint.bombs - 1, hint.bombs + 1, hint.squares.clone())
+                            .ok_or(format!("liar: digit {} has no square around", hint.bombs))?;
+                    }
+                },
+                None if clue.trim().starts_with("liar one ") => {
+                    if self.hints[..digit_hints].iter().any(|h| h.alt.is_some()) {
+                        return Err(format!("{}: not with liar", clue.trim()));
+                    }
+                    for s in self.lie_squares(clue)? {
+                        let (x, y, z) = self.s2xyz(s);
+                        let mut hint = self.bomb2hint(x, y, z, self.digits[s].unwrap());
+                        hint.lie = Some(lies);
+                        let made = self.hints[..digit_hints].iter()
+                            .position(|h| h.valid && h.lie.is_none() && h.bombs == hint.bombs && h.squares == hint.squares);
+                        match made {
+                            Some(h) => self.hints[h].lie = hint.lie,
+                            None if hint.squares.is_empty() => return Err(format!("{}: no square around {},{}", clue.trim(), x, y)),
+                            None => self.add_hint(hint),  // '.' of a classic grid makes no hint
+                        }
+                    }
+                    lies += 1;
+                },
+                None => {
+                    let hint = self.parse_clue(clue)?;
+                    self.add_hint(hint);
+                },
+            }
+            self.clues.push(clue.trim().to_string());
+        }
+
+        // digits and clues must fit in their squares, once the range is known,
+        // with the grid's '*' counted: the rules expect one count of a liar hint to fit
+        let (lo, hi) = self.range;
+        for hint in self.hints.iter().filter(|h| h.valid && h.lie.is_none()) {
+            let known: i32 = hint.squares.iter().filter_map(|&s| self.squares[s].state.value()).sum();
+            let unknown = hint.squares.iter().filter(|&&s| self.squares[s].state == SquareState::Unknown).count() as i32;
+            let fits = |bombs: i32| bombs - known >= unknown*lo && bombs - known <= unknown*hi;
+            if ! fits(hint.bombs) && ! hint.alt.is_some_and(fits) {
+                return Err(format!("hint {}: bombs do not fit in {} squares", hint, hint.squares.len()));
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_clue(&self, clue: &str) -> Result<Hint, String> {
+        if clue.trim().starts_with("liar ") {
+            let hint = self.parse_clue(&clue.trim()["liar ".len()..])?;
+            return Hint::either(hint.bombs - 1, hint.bombs + 1, hint.squares).ok_or(format!("bad clue '{}'", clue));
+        }
+        let words: Vec<&str> = clue.split_whitespace().collect();
+        let bad = || format!("bad clue '{}'", clue);
+        let number = |i: usize| words.get(i).and_then(|n| n.parse::<u32>().ok()).ok_or(bad());
+        let sum = |i: usize| words.get(i).and_then(|n| n.parse::<i32>().ok()).ok_or(bad());
+
+        let (bombs, squares): (i32, Vec<usize>) = match words.first() {
+            Some(&"row") if words.len() == 3 && self.d == 1 => {
+                let y = number(1)?;
+                if y >= self.h { return Err(bad()); }
+                (sum(2)?, (0..self.w).map(|x| self.xy2s(x, y)).collect())
+            },
+            Some(&"col") if words.len() == 3 && self.d == 1 => {
+                let x = number(1)?;
+                if x >= self.w { return Err(bad()); }
+                (sum(2)?, (0..self.h).map(|y| self.xy2s(x, y)).collect())
+            },
+            Some(&"region") if words.len() > 2 => {
+                let squares = words[2..].iter().map(|xy| self.parse_xyz(xy).ok_or(bad())).collect::<Result<_,_>>()?;
+                (sum(1)?, squares)
+            },
+            _ => return Err(bad()),
+        };
+        Ok(Hint::new(bombs, squares))
+    }
+
+    // digit squares of a "liar one" clue
+    fn lie_squares(&self, clue: &str) -> Result<Vec<usize>, String> {
+        let bad = || format!("bad clue '{}'", clue.trim());
+        let words: Vec<&str> = clue.split_whitespace().collect();
+        if words.len() < 3 || words[..2] != ["liar", "one"] {
+            return Err(bad());
+        }
+        let mut squares: Vec<usize> = Vec::with_capacity(words.len()-2);
+        for xy in words[2..].iter() {
+            let s = self.parse_xyz(xy).ok_or(bad())?;
+            if self.digits[s].is_none() || squares.contains(&s) {
+                return Err(format!("{}: {} is no digit or given twice", clue.trim(), xy));
+            }
+            squares.push(s);
+        }
+        Ok(squares)
+    }
+
+    // "x,y", or "x,y,z" on 3D boards
+    fn parse_xyz(&self, xy: &str) -> Option<usize> {
+        let xy: Vec<u32> = xy.split(',').map(|n| n.parse::<u32>()).collect::<Result<_,_>>().ok()?;
+        let z = if xy.len() == 3 { xy[2] } else { 0 };
+        if xy.len() < 2 || xy.len() > 3 || xy[0] >= self.w || xy[1] >= self.h || z >= self.d { return None; }
+        Some(self.xyz2s(xy[0], xy[1], z))
+    }
+
+    // mine layout of a fully solved board
+    fn to_layout(&self) -> Result<Layout, String> {
+        if self.d > 1 {
+            return Err("3D board: no layout format".to_string());
+        }
+        let mut layout = Layout::new(self.w, self.h);
+        for (s,square) in self.squares.iter().enumerate() {
+            match square.state {
+                SquareState::Bomb => layout.mines[s] = true,
+                SquareState::Empty => {},
+                SquareState::Mines(v) => return Err(format!("square {} holds {} mines", s, v)),
+                SquareState::Unknown => return Err(format!("square {} is not solved", s)),
+            }
+        }
+        Ok(layout)
+    }
+
+    // variant boards: "x y mines" for each square holding some
     fn print_bombs(&self) {
-        let mut found_bombs = 0;
+        if self.d > 1 {
+            let bombs = self.bombs_xyz();
+            for &(x,y,z) in bombs.iter() {
+                println!("{} {} {}", x, y, z);
+            }
+            log!(LogLevel::Summary, "found", bombs = bombs.len(), of = self.total());
+            return;
+        }
+        if self.range != (0, 1) {
+            let mines = self.mines_xy();
+            for &(x,y,v) in mines.iter() {
+                println!("{} {} {}", x, y, v);
+            }
+            log!(LogLevel::Summary, "found", squares = mines.len(), of = self.total());
+            return;
+        }
+        let bombs = self.bombs_xy();
+        for &(x,y) in bombs.iter() {
+            println!("{} {}", x, y);
+        }
+        log!(LogLevel::Summary, "found", bombs = bombs.len(), of = self.total());
+    }
+
+    // bombs as read: "n", "LO-HI" or '?'
+    fn total(&self) -> String {
+        if self.bombs_max == u32::MAX {
+            "?".to_string()
+        } else if self.bombs_max != self.bombs {
+            format!("{}-{}", self.bombs, self.bombs_max)
+        } else {
+            format!("{}", self.bombs)
+        }
+    }
+
+    // a number when known, else as total()
+    fn total_json(&self) -> Json {
+        if self.bombs_max == self.bombs { Json::Int(self.bombs as i64) } else { Json::Str(self.total()) }
+    }
+
+    // "wxh", "wxhxd" for 3D boards
+    fn size(&self) -> String {
+        if self.d > 1 { format!("{}x{}x{}", self.w, self.h, self.d) } else { format!("{}x{}", self.w, self.h) }
+    }
+
+    // 3D boards: x, then y, then z
+    fn bombs_xyz(&self) -> Vec<(u32,u32,u32)> {
+        let mut bombs: Vec<(u32,u32,u32)> = Vec::new();
+        for x in 0..self.w {
+            for y in 0..self.h {
+                for z in 0..self.d {
+                    if self.squares[self.xyz2s(x,y,z)].state == SquareState::Bomb {
+                        bombs.push((x,y,z));
+                    }
+                }
+            }
+        }
+        bombs
+    }
+
+    // known non-zero squares in print_bombs() order
+    fn mines_xy(&self) -> Vec<(u32,u32,i32)> {
+        let mut mines: Vec<(u32,u32,i32)> = Vec::new();
+        for x in 0..self.w {
+            for y in 0..self.h {
+                match self.squares[self.xy2s(x,y)].state.value() {
+                    Some(v) if v != 0 => mines.push((x,y,v)),
+                    _ => {},
+                }
+            }
+        }
+        mines
+    }
+
+    // known bombs in print_bombs() order
+    fn bombs_xy(&self) -> Vec<(u32,u32)> {
+        let mut bombs: Vec<(u32,u32)> = Vec::new();
         for x in 0..self.w {
             for y in 0..self.h {
                 if self.squares[self.xy2s(x,y)].state == SquareState::Bomb {
-                    println!("{} {}", x, y);
-                    found_bombs += 1;
+                    bombs.push((x,y));
                 }
             }
         }
-        eprintln!("Found {}/{} bombs", found_bombs, self.bombs);
+        bombs
     }
 
     // converstions Square ID <--> [X,Y]
     // Used: - when reading a grid and making Hints
     //       - when printing answer
     fn xy2s(&self, x: u32, y: u32) -> usize {
-        assert!(x < self.w && y < self.h);
-        (self.w*y + x ) as usize
+        self.xyz2s(x, y, 0)
     }
-    
-    fn bomb2hint(&self, x: u32, y: u32, bombs: u32) -> Hint {
-        let mut squares :Vec<usize> = Vec::with_capacity(8);
-
-        if x > 0        && y > 0        { squares.push(self.xy2s(x-1,y-1)); }
-        if                 y > 0        { squares.push(self.xy2s(x  ,y-1)); }
-        if x < self.w-1 && y > 0        { squares.push(self.xy2s(x+1,y-1)); }
-        if x < self.w-1                 { squares.push(self.xy2s(x+1,y  )); }
-        if x < self.w-1 && y < self.h-1 { squares.push(self.xy2s(x+1,y+1)); }
-        if                 y < self.h-1 { squares.push(self.xy2s(x  ,y+1)); }
-        if x > 0        && y < self.h-1 { squares.push(self.xy2s(x-1,y+1)); }
-        if x > 0                        { squares.push(self.xy2s(x-1,y  )); }
+
+    // layers one after another
+    fn xyz2s(&self, x: u32, y: u32, z: u32) -> usize {
+        assert!(x < self.w && y < self.h && z < self.d);
+        (self.w*self.h*z + self.w*y + x) as usize
+    }
+
+    fn s2xyz(&self, s: usize) -> (u32, u32, u32) {
+        let s = s as u32;
+        (s % self.w, s / self.w % self.h, s / (self.w*self.h))
+    }
+
+    // 8 neighbours, 26 on 3D boards
+    fn bomb2hint(&self, x: u32, y: u32, z: u32, bombs: i32) -> Hint {
+        let mut squares :Vec<usize> = Vec::with_capacity(if self.d > 1 { 26 } else { 8 });
+
+        for nz in z.saturating_sub(1)..cmp::min(z+2, self.d) {
+            for ny in y.saturating_sub(1)..cmp::min(y+2, self.h) {
+                for nx in x.saturating_sub(1)..cmp::min(x+2, self.w) {
+                    if (nx, ny, nz) != (x, y, z) { squares.push(self.xyz2s(nx, ny, nz)); }
+                }
+            }
+        }
 
         Hint::new(bombs, squares)
     }
@@ -254,6 +901,7 @@ impl Board {
             self.squares[*s].add_hint(h);
         }
         self.hints.push(hint);
+        self.stats.hint_added();
     }
 
     fn rm_hint(&mut self, h: usize) {
@@ -263,9 +911,10 @@ impl Board {
         }
         
         self.hints[h].valid = false;
+        self.stats.hint_removed();
 
         // tidy vector, not chaning position of any valid hint
-        while self.hints.len() > 0 {
+        while !self.hints.is_empty() {
             if ! self.hints.last().unwrap().valid {
                 let last_idx = self.hints.len()-1;
                 self.hints.remove(last_idx);
@@ -291,13 +940,160 @@ impl Board {
     }
 
     fn update_delta(&mut self, delta: &mut Delta) {
-        eprintln!("{}", delta);
+        log!(LogLevel::Delta, "delta", delta = delta);
+        if ! delta.is_empty() {
+            if self.history.recording {
+                let undo = self.delta_undo(delta);
+                self.apply_delta(delta);
+                self.record(Step::Delta(delta.clone()), undo);
+            } else {
+                self.apply_delta(delta);
+            }
+        }
+        delta.reset();
+        log!(LogLevel::Board, "board", board = self);
+    }
+
+    fn apply_delta(&mut self, delta: &Delta) {
         for h in delta.hints2rm.iter() { self.rm_hint(*h); }
-        while let Some(hint) = delta.hints2add.pop() { self.add_hint(hint); }
+        for hint in delta.hints2add.iter().rev() { self.add_hint(hint.clone()); }
         for s in delta.squares2bomb.iter() { self.set_square_state(*s, SquareState::Bomb); }
         for s in delta.squares2empty.iter() { self.set_square_state(*s, SquareState::Empty); }
-        delta.reset();
-        eprintln!("{}", self);
+        for &(s, v) in delta.squares2mines.iter() { self.set_square_state(s, SquareState::Mines(v)); }
+    }
+
+    // what apply_delta() is about to change
+    fn delta_undo(&self, delta: &Delta) -> Undo {
+        let mut touched: Vec<usize> = Vec::new();
+        for h in delta.hints2rm.iter() { touched.extend_from_slice(&self.hints[*h].squares); }
+        for hint in delta.hints2add.iter() { touched.extend_from_slice(&hint.squares); }
+        touched.extend_from_slice(&delta.squares2bomb);
+        touched.extend_from_slice(&delta.squares2empty);
+        touched.extend(delta.squares2mines.iter().map(|&(s,_)| s));
+        touched.sort();
+        touched.dedup();
+
+        Undo {
+            squares: touched.iter()
+                .map(|&s| (s, self.squares[s].state, self.squares[s].hints.clone(), self.squares[s].on_stack))
+                .collect(),
+            hints: delta.hints2rm.iter().map(|&h| (h, self.hints[h].clone())).collect(),
+            hints_len: self.hints.len(),
+            stack_len: self.stack.len(),
+        }
+    }
+
+    // update_square() recorded in history
+    fn push_square(&mut self, s: usize) {
+        if ! self.history.recording {
+            self.update_square(s);
+            return;
+        }
+        let undo = self.push_undo(s);
+        self.update_square(s);
+        self.record(Step::Push(s), undo);
+    }
+
+    fn push_undo(&self, s: usize) -> Undo {
+        let square = &self.squares[s];
+        Undo { squares: vec![(s, square.state, square.hints.clone(), square.on_stack)],
+               hints: vec![], hints_len: self.hints.len(), stack_len: self.stack.len() }
+    }
+
+    fn pop_square(&mut self) -> Option<usize> {
+        let s = self.stack.pop()?;
+        self.squares[s].on_stack = false;
+        self.stats.pops += 1;
+        if self.history.recording {
+            let undo = Undo { squares: vec![], hints: vec![], hints_len: self.hints.len(), stack_len: self.stack.len()+1 };
+            self.record(Step::Pop(s), undo);
+        }
+        Some(s)
+    }
+
+    // a new step drops the redo list
+    fn record(&mut self, step: Step, undo: Undo) {
+        self.history.done.push((step, undo));
+        self.history.undone.clear();
+    }
+
+/* ------------------------------------------------------------- Impl: Board: undo/redo */
+    // keeps history from now on, see History
+    fn record_history(&mut self) {
+        self.history.recording = true;
+    }
+
+    // position in history to come back to with undo_to()
+    fn checkpoint(&self) -> usize {
+        self.history.done.len()
+    }
+
+    // reverts last Step, returns FALSE if there is nothing to undo
+    fn undo(&mut self) -> bool {
+        let (step, undo) = match self.history.done.pop() {
+            Some(done) => done,
+            None => return false,
+        };
+
+        if let Step::Pop(s) = step {
+            self.stack.push(s);
+            self.squares[s].on_stack = true;
+        }
+
+        // hints removed from the end of the vector come back as invalid ones
+        self.hints.truncate(undo.hints_len);
+        while self.hints.len() < undo.hints_len {
+            self.hints.push(Hint::new(0, vec![]));
+        }
+        for (h, hint) in undo.hints {
+            self.hints[h] = hint;
+        }
+
+        for (s, state, hints, on_stack) in undo.squares {
+            self.squares[s].state = state;
+            self.squares[s].hints = hints;
+            self.squares[s].on_stack = on_stack;
+        }
+        self.stack.truncate(undo.stack_len);
+        self.stats.hints_alive = self.hints.iter().filter(|h| h.valid).count();
+
+        self.history.undone.push(step);
+        true
+    }
+
+    // applies last undone Step again, returns FALSE if there is nothing to redo
+    fn redo(&mut self) -> bool {
+        let step = match self.history.undone.pop() {
+            Some(step) => step,
+            None => return false,
+        };
+
+        let undo = match step {
+            Step::Pop(s) => {
+                assert_eq!(self.stack.pop(), Some(s));
+                self.squares[s].on_stack = false;
+                Undo { squares: vec![], hints: vec![], hints_len: self.hints.len(), stack_len: self.stack.len()+1 }
+            },
+            Step::Push(s) => {
+                let undo = self.push_undo(s);
+                self.update_square(s);
+                undo
+            },
+            Step::Delta(ref delta) => {
+                let undo = self.delta_undo(delta);
+                self.apply_delta(delta);
+                undo
+            },
+        };
+        self.history.done.push((step, undo));
+        true
+    }
+
+    fn undo_to(&mut self, checkpoint: usize) {
+        assert!(checkpoint <= self.checkpoint());
+        while self.checkpoint() > checkpoint {
+            self.undo();
+        }
     }
     
 /* ------------------------------------------------------------- Impl: Board: Hint processing */
@@ -305,79 +1101,113 @@ impl Board {
     // returns TRUE if hint should be released
     fn process_hint(&self, h: usize, delta: &mut Delta) {
         let hint = & self.hints[h];
-        eprintln!("process_hint({}={})", h, hint);
+        log!(LogLevel::Delta, "process_hint", h = h, hint = hint);
+        if hint.lie.is_some() {
+            return;  // may be the wrong one, see process_search()
+        }
 
         // "some squares already defined"
         // Ex: 4[??*.] -> 2[??]
         if ! hint.squares.iter().all(|&s| self.squares[s].state == SquareState::Unknown) {
-            let mut squares2 : Vec<usize>= Vec::with_capacity(hint.bombs as usize);
-            let mut bombs2 = hint.bombs;
-            let mut squares_it = hint.squares.iter();
-            while let Some(s) = squares_it.next() {
-                match self.squares[*s].state {
-                    SquareState::Unknown => squares2.push(*s),
-                    SquareState::Empty => {},
-                    SquareState::Bomb => bombs2 -= 1,
+            let mut squares2 : Vec<usize>= Vec::with_capacity(hint.squares.len());
+            let mut known = 0;
+            for s in hint.squares.iter() {
+                match self.squares[*s].state.value() {
+                    None => squares2.push(*s),
+                    Some(v) => known += v,
                 }
             }
-            delta.add_hint(Hint::new(bombs2, squares2));
+            delta.add_hint(match hint.alt {
+                None => Hint::new(hint.bombs - known, squares2),
+                Some(alt) => Hint::either(hint.bombs - known, alt - known, squares2).expect("liar hint: neither count fits"),
+            });
             delta.rm_hint(h);
+            self.stats.rule(Rule::Known);
             return;
         }
 
-        // "no bombs"
+        // "liar", one of the two counts doesn't fit: the other one it is
+        // Ex: 1|3[??] -> 1[??]
+        let (lo, hi) = self.range;
+        if hint.alt.is_some() {
+            let sums = hint.sums(lo, hi);
+            assert!(!sums.is_empty());
+            if sums.len() == 1 {
+                delta.add_hint(Hint::new(sums[0], hint.squares.clone()));
+                delta.rm_hint(h);
+                self.stats.rule(Rule::Liar);
+            }
+            return;  // both may be, see process_search()
+        }
+
+        // "no bombs", or all at the lowest value with negative mines
         // Ex: 0[.?*?] -> 00*0
-        if hint.bombs == 0 {
+        let n = hint.squares.len() as i32;
+        if hint.bombs == n*lo {
             for s in &hint.squares {
-                delta.set_square_empty(*s);
+                delta.set_square_value(*s, lo);
             }
             delta.rm_hint(h);
+            self.stats.rule(Rule::NoBombs);
             return;
         }
 
-        // "all bombs"
+        // "all bombs", as many as a square can hold
         // Ex: 2[??] -> **
-        if hint.bombs  == hint.squares.len() as u32 {
+        if hint.bombs == n*hi {
             for s in &hint.squares {
-                delta.set_square_bomb(*s);
+                delta.set_square_value(*s, hi);
             }
             delta.rm_hint(h);
+            self.stats.rule(Rule::AllBombs);
             return;
         }
+
+        // "single square", only left with multi-mines (lo < bombs < hi)
+        // Ex: 1[?] with range -1..1 -> 1
+        if n == 1 {
+            delta.set_square_value(hint.squares[0], hint.bombs);
+            delta.rm_hint(h);
+            self.stats.rule(Rule::Single);
+        }
     }
 
     fn process_hint_pair(&self, h1: usize, h2: usize, delta: &mut Delta) {
         let hint1 = & self.hints[h1];
         let hint2 = & self.hints[h2];
+        if hint1.alt.is_some() || hint2.alt.is_some() || hint1.lie.is_some() || hint2.lie.is_some() {
+            return;  // liar hints, see process_search()
+        }
         
         // handy assumption for "h1 inside h2" case
         if hint1.squares.len() > hint2.squares.len() {
             self.process_hint_pair(h2, h1, delta);
             return;
         }
-        eprintln!("process_hint_pair({}={}, {}={})", h1, hint1, h2, hint2);
+        log!(LogLevel::Delta, "process_hint_pair", h1 = h1, hint1 = hint1, h2 = h2, hint2 = hint2);
                                          // Ex: h1=[1,2,4] h2=[1,3,4,8]
         
         let (squares_h1, squares_h2, squares_both) = Hint::compare_squares(hint1, hint2);
-        assert!(squares_both.len() > 0); // hints must be related to the same square
+        assert!(!squares_both.is_empty()); // hints must be related to the same square
     
         // duplicated -> delete one
         // Ex: 2[11,12,13] -> 2[11,12,13]
         //     2[11,12,13]
-        if squares_h1.len() == 0 && squares_h2.len() == 0 {
+        if squares_h1.is_empty() && squares_h2.is_empty() {
             delta.rm_hint(h1);
+            self.stats.rule(Rule::Duplicate);
             return;
         }
 
         // h1 inside h2
         // Ex: 1[10,11]       -> 1[10,11]
         //     3[10,11,12,13] ->        2[12,13]
-        if squares_h1.len() == 0 {
-            assert!(hint1.bombs <= hint2.bombs);
-            assert!(squares_h2.len() > 0);
+        if squares_h1.is_empty() {
+            assert!(!squares_h2.is_empty());
            
             delta.add_hint( Hint::new(hint2.bombs-hint1.bombs, squares_h2) );
             delta.rm_hint(h2);
+            self.stats.rule(Rule::Inside);
             return;
         }
 
@@ -388,29 +1218,24 @@ impl Board {
         // h1: [   A   ][   B   ]            <- h1 hint with M bombs (M=A+B)
         // h2:          [   B   ][   C   ]   <- h2 hint with N bombs (N=B+C)
         //
-        // So: B >= M-X, B >= N-Z            <- if can't fit all M bombs in X, or N bombs in Z
-        //     B <= Y, B >= 0                <- of course
-        //     B <= M, B <= N
+        // With every square holding lo..hi mines (0..1 classic):
+        // So: B >= M-X*hi, B >= N-Z*hi      <- if can't fit all M bombs in X, or N bombs in Z
+        //     B <= Y*hi, B >= Y*lo          <- of course
+        //     B <= M-X*lo, B <= N-Z*lo
         //
         // Ex: 1[10,11,12,13,14]     -> 0[10,11,12]      1[15]
         //              2[13,14,15]  ->           1[13,14]
-        let mut both_min = 0u32;
-        if hint1.bombs >= squares_h1.len() as u32 {
-            both_min = cmp::max(both_min, hint1.bombs - squares_h1.len() as u32);
-        }
-        if hint2.bombs >= squares_h2.len() as u32 {
-            both_min = cmp::max(both_min, hint2.bombs - squares_h2.len() as u32);
-        }
-        let mut both_max = squares_both.len() as u32;
-        both_max = cmp::min(both_max, hint1.bombs );
-        both_max = cmp::min(both_max, hint2.bombs );
+        let (lo, hi) = self.range;
+        let (x, y, z) = (squares_h1.len() as i32, squares_both.len() as i32, squares_h2.len() as i32);
+        let both_min = cmp::max(y*lo, cmp::max(hint1.bombs - x*hi, hint2.bombs - z*hi));
+        let both_max = cmp::min(y*hi, cmp::min(hint1.bombs - x*lo, hint2.bombs - z*lo));
         if both_min == both_max {
             delta.add_hint( Hint::new(both_min, squares_both) );
             delta.add_hint( Hint::new(hint1.bombs - both_min, squares_h1) );
             delta.add_hint( Hint::new(hint2.bombs - both_min, squares_h2) );
             delta.rm_hint(h1);
             delta.rm_hint(h2);
-            return;
+            self.stats.rule(Rule::Intersect);
         }
     }
 
@@ -418,173 +1243,2588 @@ impl Board {
         let mut n_bombs = 0;
         let mut n_unknown = 0;
         for square in self.squares.iter() {
-            match square.state {
-                SquareState::Bomb => n_bombs += 1,
-                SquareState::Unknown => n_unknown += 1,
-                _ => {},
+            match square.state.value() {
+                Some(v) => n_bombs += v,
+                None => n_unknown += 1,
             }
         }
 
-        if n_bombs < self.bombs && n_bombs+n_unknown == self.bombs {
-            for s in 0..(self.w*self.h) as usize {
+        // all unknown at the highest value, or (variants and total ranges only) at the lowest one
+        // an unknown total (bombs_max u32::MAX) never fits either
+        let (lo, hi) = self.range;
+        let (least, most) = (self.bombs as i64 - n_bombs as i64, self.bombs_max as i64 - n_bombs as i64);
+        let n = n_unknown as i64;
+        let value = if n_unknown == 0 { None }
+            else if least == n*hi as i64 { Some(hi) }
+            else if most == n*lo as i64 && (self.range != (0, 1) || self.bombs_max != self.bombs) { Some(lo) }
+            else { None };
+        if let Some(value) = value {
+            for s in 0..self.squares.len() {
                 if self.squares[s].state == SquareState::Unknown {
-                    delta.set_square_bomb(s);
+                    delta.set_square_value(s, value);
                 }
             }
+            self.stats.rule(Rule::Remaining);
         }
     }
 
-    fn process_stack(&mut self) {
-        let mut delta = Delta::new();
-        eprintln!("process_stack begin, board={}", self);
+    // one square popped from the stack, may put it back
+    fn process_square(&mut self, s: usize, delta: &mut Delta) {
+        log!(LogLevel::Delta, "square", s = s, square = format!("{:?}", self.squares[s]));
 
+        // process each Hint
+        let start = Instant::now();
+        for h in &self.squares[s].hints {
+            self.process_hint(*h, delta);
+            if ! delta.is_empty() { break; } // for easier debugging
+        }
+        self.stats.time(Phase::Hints, start);
 
-        'next_square: while let Some(s) = self.stack.pop() {
-            eprintln!("square {}={:?}", s, self.squares[s]);
-            self.squares[s].on_stack = false;
+        // do delta
+        if ! delta.is_empty() {
+            self.update_delta(delta);
+            self.push_square(s);
+            return;
+        }
 
-            // process each Hint
-            for h in &self.squares[s].hints {
-                self.process_hint(*h, &mut delta);
-                if ! delta.is_empty() { break; } // for easier debugging
+        // process Hint pairs
+        let start = Instant::now();
+        'pairs: for (i, h1) in self.squares[s].hints.iter().enumerate() {
+            for (_, h2) in self.squares[s].hints.iter().enumerate().filter(|&(j,_)| j>i) {
+                self.process_hint_pair(*h1, *h2, delta);
+                if ! delta.is_empty() { break 'pairs; } // for easier debugging
             }
+        }
+        self.stats.time(Phase::Pairs, start);
+       
+        // do delta
+        if ! delta.is_empty() {
+            self.update_delta(delta);
+            self.push_square(s);
+        }
+    }
 
-            // do delta
-            if ! delta.is_empty() {
-                self.update_delta(&mut delta);
-                self.update_square(s);
-                continue 'next_square;
+    // pops at most n squares and stops, so a snapshot can be taken mid-solve
+    // returns FALSE if the stack ran empty
+    fn process_stack_steps(&mut self, n: usize) -> bool {
+        let mut delta = Delta::new();
+        for _ in 0..n {
+            match self.pop_square() {
+                Some(s) => self.process_square(s, &mut delta),
+                None => return false,
             }
+        }
+        ! self.stack.is_empty()
+    }
+
+    fn process_stack(&mut self) {
+        self.solve(&Limits::default());
+    }
 
-            // process Hint pairs
-            'pairs: for (i, h1) in self.squares[s].hints.iter().enumerate() {
-                for (_, h2) in self.squares[s].hints.iter().enumerate().filter(|&(j,_)| j>i) {
-                    self.process_hint_pair(*h1, *h2, &mut delta);
-                    if ! delta.is_empty() { break 'pairs; } // for easier debugging
+    // stack and search until nothing more is found, or a limit is reached
+    fn deduce(&mut self, limits: &Limits) -> Option<Stop> {
+        let mut delta = Delta::new();
+        let mut steps = 0u64;
+        loop {
+            while ! self.stack.is_empty() {
+                if let Some(stop) = limits.take(steps) {
+                    log!(LogLevel::Summary, "incomplete", reason = format!("{:?}", stop), steps = steps, stack = self.stack.len());
+                    return Some(stop);
                 }
+                let s = self.pop_square().unwrap();
+                self.process_square(s, &mut delta);
+                steps += 1;
             }
-           
-            // do delta
-            if ! delta.is_empty() {
+
+            // liar hints left by the rules
+            let start = Instant::now();
+            let stop = self.process_search(&mut delta, limits, steps);
+            self.stats.time(Phase::Search, start);
+            if let Some(stop) = stop {
                 self.update_delta(&mut delta);
-                self.update_square(s);
+                log!(LogLevel::Summary, "incomplete", reason = format!("{:?}", stop), steps = steps, stack = self.stack.len());
+                return Some(stop);
             }
+            if delta.is_empty() { return None; }
+            self.update_delta(&mut delta);
+        }
+    }
+
+    // process_stack() checking the limits before each square
+    fn solve(&mut self, limits: &Limits) -> Outcome {
+        let mut delta = Delta::new();
+        log!(LogLevel::Summary, "begin", w = self.w, h = self.h, bombs = self.total(), stack = self.stack.len());
+        log!(LogLevel::Board, "board", board = self);
+
+        if let Some(stop) = self.deduce(limits) {
+            self.stats.log();
+            return Outcome::Incomplete(stop);
         }
 
         // x uncovered bombs on x unknown squares
+        let start = Instant::now();
         self.process_remaining(&mut delta);
+        self.stats.time(Phase::Remaining, start);
         self.update_delta(&mut delta);
         
-        eprintln!("process_stack end, board={}", self);
+        log!(LogLevel::Summary, "end", deltas = self.stats.deltas(), unknown = self.squares.iter().filter(|sq| sq.state == SquareState::Unknown).count());
+        log!(LogLevel::Board, "board", board = self);
+        self.stats.log();
+        Outcome::Complete
+    }
+
+}
+
+/* ------------------------------------------------------------- Impl: Board: search */
+// Liar hints (bombs OR alt) give the rules nothing while both counts fit.
+// Unknown squares around them are tried exhaustively instead, against every
+// hint linked to them: a square holding the same value in all solutions is forced.
+// "liar one" hints are left alone by the rules too. A solution has exactly one
+// wrong hint in each of their groups, so the whole group is searched together;
+// a hint right in all solutions becomes a plain one.
+const SEARCH_NODES: u64 = 1_000_000;  // given up above, Stop::Nodes
+const SEARCH_CHECK: u64 = 4096;       // nodes between two checks of the Limits
+
+struct Search {
+    lo: i32,
+    hi: i32,
+    values: Vec<i32>,            // by square, as assigned
+    var_hints: Vec<Vec<usize>>,  // by square: hints it is in
+    counts: Vec<Vec<i32>>,       // by hint: sums it may have, known squares taken off
+    sum: Vec<i32>,               // by hint: of assigned squares
+    left: Vec<i32>,              // by hint: squares not assigned yet
+    seen: Vec<u64>,              // by square: bit v-lo set if some solution has v
+    lie: Vec<Option<usize>>,     // by hint: its "liar one" group, renumbered
+    liars: Vec<i32>,             // by group: wrong hints with all squares assigned
+    told: Vec<u8>,               // by hint: bit 0 set if right in some solution, bit 1 if wrong
+    solutions: u64,
+    nodes: u64,
+    stop: Option<Stop>,          // why run() gave up
+}
+
+impl Search {
+    fn fits(&self, h: usize) -> bool {
+        let (lo, hi) = (self.sum[h] + self.left[h]*self.lo, self.sum[h] + self.left[h]*self.hi);
+        let right = self.counts[h].iter().any(|&c| c >= lo && c <= hi);
+        match self.lie[h] {
+            None => right,
+            Some(g) => self.left[h] == 0 || right || self.liars[g] == 0,  // done ones are in liars
+        }
+    }
+
+    // "liar one" hint with all squares assigned, and not its count
+    fn wrong(&self, h: usize) -> bool {
+        self.lie[h].is_some() && self.left[h] == 0 && self.sum[h] != self.counts[h][0]
+    }
+
+    fn assign(&mut self, h: usize, v: i32) {
+        self.sum[h] += v;
+        self.left[h] -= 1;
+        if self.wrong(h) { self.liars[self.lie[h].unwrap()] += 1; }
+    }
+
+    fn unassign(&mut self, h: usize, v: i32) {
+        if self.wrong(h) { self.liars[self.lie[h].unwrap()] -= 1; }
+        self.sum[h] -= v;
+        self.left[h] += 1;
+    }
+
+    // assigns square i and on, returns FALSE when out of nodes or limits, see stop
+    fn run(&mut self, i: usize, limits: &Limits, steps: u64) -> bool {
+        self.nodes += 1;
+        if self.nodes > SEARCH_NODES {
+            self.stop = Some(Stop::Nodes);
+            return false;
+        }
+        if self.nodes.is_multiple_of(SEARCH_CHECK) {
+            self.stop = limits.check(steps);
+            if self.stop.is_some() { return false; }
+        }
+        if i == self.values.len() {
+            if self.liars.iter().any(|&n| n != 1) { return true; }
+            self.solutions += 1;
+            for (seen, &v) in self.seen.iter_mut().zip(self.values.iter()) {
+                *seen |= 1 << (v - self.lo);
+            }
+            for h in 0..self.told.len() {
+                if self.lie[h].is_some() { self.told[h] |= if self.wrong(h) { 2 } else { 1 }; }
+            }
+            return true;
+        }
+
+        let hints = self.var_hints[i].clone();
+        for v in self.lo..=self.hi {
+            self.values[i] = v;
+            for &h in hints.iter() { self.assign(h, v); }
+            let fits = hints.iter().all(|&h| self.fits(h) && self.lie[h].is_none_or(|g| self.liars[g] <= 1));
+            let go_on = ! fits || self.run(i+1, limits, steps);
+            for &h in hints.iter() { self.unassign(h, v); }
+            if ! go_on { return false; }
+        }
+        true
+    }
+}
+
+impl Board {
+    // what was found until a group of squares stopped the search, if one did
+    fn process_search(&self, delta: &mut Delta, limits: &Limits, steps: u64) -> Option<Stop> {
+        let searched = |h: &Hint| h.valid && (h.alt.is_some() || h.lie.is_some());
+        if ! self.hints.iter().any(searched) { return None; }
+
+        // each group of linked squares on its own, so that unrelated ones don't multiply solutions
+        let mut in_hints = vec![false; self.hints.len()];
+        let mut var_of: Vec<Option<usize>> = vec![None; self.squares.len()];
+        let mut found = false;
+        let mut stop = None;
+        for h in 0..self.hints.len() {
+            if searched(&self.hints[h]) && ! in_hints[h] {
+                match self.search_group(h, &mut in_hints, &mut var_of, delta, limits, steps) {
+                    Ok(forced) => found |= forced,
+                    Err(s) => { stop = Some(s); break; },
+                }
+            }
+        }
+        if found {
+            self.stats.rule(Rule::Search);
+        }
+        stop
+    }
+
+    // returns TRUE if some square is forced
+    fn search_group(&self, seed: usize, in_hints: &mut [bool], var_of: &mut [Option<usize>], delta: &mut Delta,
+                    limits: &Limits, steps: u64) -> Result<bool, Stop> {
+        // liar hint, and hints sharing unknown squares or a "liar one" group with it, transitively
+        let mut hints: Vec<usize> = vec![seed];
+        in_hints[seed] = true;
+        let mut vars: Vec<usize> = Vec::new();
+        let mut i = 0;
+        while i < hints.len() {
+            if let Some(lie) = self.hints[hints[i]].lie {
+                for (h, hint) in self.hints.iter().enumerate() {
+                    if ! in_hints[h] && hint.valid && hint.lie == Some(lie) { in_hints[h] = true; hints.push(h); }
+                }
+            }
+            for &s in self.hints[hints[i]].squares.iter() {
+                if self.squares[s].state != SquareState::Unknown || var_of[s].is_some() { continue; }
+                var_of[s] = Some(vars.len());
+                vars.push(s);
+                for &h in self.squares[s].hints.iter() {
+                    if ! in_hints[h] { in_hints[h] = true; hints.push(h); }
+                }
+            }
+            i += 1;
+        }
+
+        let group: Vec<&Hint> = hints.iter().map(|&h| &self.hints[h]).collect();
+        let mut search = self.new_search(&group, vars.len(), var_of);
+        let finished = search.run(0, limits, steps);
+        log!(LogLevel::Delta, "search", squares = vars.len(), hints = hints.len(), solutions = search.solutions, nodes = search.nodes);
+        if ! finished { return Err(search.stop.unwrap()); }
+        if search.solutions == 0 { return Ok(false); }
+
+        let lo = self.range.0;
+        let mut found = false;
+        for (&s, &seen) in vars.iter().zip(search.seen.iter()) {
+            if seen.count_ones() == 1 {
+                delta.set_square_value(s, lo + seen.trailing_zeros() as i32);
+                found = true;
+            }
+        }
+        for (&h, &told) in hints.iter().zip(search.told.iter()) {
+            if self.hints[h].lie.is_some() && told == 1 {
+                delta.rm_hint(h);
+                delta.add_hint(Hint::new(self.hints[h].bombs, self.hints[h].squares.clone()));
+                found = true;
+            }
+        }
+        Ok(found)
+    }
+
+    // over the n squares numbered in var_of, the others count as known
+    fn new_search(&self, hints: &[&Hint], n: usize, var_of: &[Option<usize>]) -> Search {
+        let (lo, hi) = self.range;
+        let mut search = Search { lo, hi, values: vec![lo; n], var_hints: vec![Vec::new(); n],
+                                  counts: Vec::new(), sum: vec![0; hints.len()], left: vec![0; hints.len()],
+                                  seen: vec![0; n], lie: Vec::new(), liars: Vec::new(), told: vec![0; hints.len()],
+                                  solutions: 0, nodes: 0, stop: None };
+        let mut groups: Vec<usize> = Vec::new();
+        for (i, hint) in hints.iter().enumerate() {
+            search.lie.push(hint.lie.map(|lie| groups.iter().position(|&g| g == lie).unwrap_or_else(|| { groups.push(lie); groups.len()-1 })));
+            let mut known = 0;
+            for &s in hint.squares.iter() {
+                match var_of[s] {
+                    Some(v) => { search.var_hints[v].push(i); search.left[i] += 1; },
+                    None => known += self.squares[s].state.value().unwrap_or(0),
+                }
+            }
+            search.counts.push(Some(hint.bombs).into_iter().chain(hint.alt).map(|c| c - known).collect());
+        }
+        search.liars = vec![0; groups.len()];
+        for h in 0..hints.len() {
+            if search.wrong(h) { search.liars[search.lie[h].unwrap()] += 1; }
+        }
+        search
+    }
+}
+
+/* ------------------------------------------------------------- Impl: Board: minimal proof */
+// The fewest revealed digits that force a square, to show a human player where to
+// look. Digits force it when it holds the same value in every solution of them
+// alone, as found by Search; the total, the clues and digits of "liar one" clues
+// are left out. Candidate sets grow from the digits around the square through
+// digits sharing unknown squares, one digit at a time, so the first forcing set
+// is a smallest one.
+const PROOF_SETS: usize = 100_000;  // digit sets tried before giving up
+
+impl Board {
+    // on a board as read: (value of square s, digit squares), None if no digits force it
+    fn proof(&self, s: usize) -> Option<(i32, Vec<usize>)> {
+        if let Some(v) = self.squares[s].state.value() {
+            return Some((v, Vec::new()));
+        }
+
+        // digits by square as from_layers() made them, and around each unknown square
+        let liar = self.clues.iter().any(|c| c == "liar");
+        let lies: Vec<usize> = self.clues.iter().filter(|c| c.starts_with("liar one "))
+            .flat_map(|c| self.lie_squares(c).unwrap()).collect();
+        let mut hints: Vec<Option<Hint>> = vec![None; self.squares.len()];
+        let mut around: Vec<Vec<usize>> = vec![Vec::new(); self.squares.len()];
+        for (p, &digit) in self.digits.iter().enumerate() {
+            let digit = match digit {
+                Some(0) if liar => continue,  // no hint was made of '.'
+                Some(_) if lies.contains(&p) => continue,  // may be the wrong one
+                Some(digit) => digit,
+                None => continue,
+            };
+            let (x, y, z) = self.s2xyz(p);
+            let mut hint = self.bomb2hint(x, y, z, digit);
+            if liar {
+                hint = Hint::either(digit - 1, digit + 1, hint.squares).unwrap();  // as add_clues() made it
+            }
+            for &q in hint.squares.iter().filter(|&&q| self.squares[q].state == SquareState::Unknown) {
+                around[q].push(p);
+            }
+            hints[p] = Some(hint);
+        }
+
+        let mut seen: std::collections::HashSet<Vec<usize>> = std::collections::HashSet::new();
+        let mut level: Vec<Vec<usize>> = around[s].iter().map(|&p| vec![p]).collect();
+        let mut var_of: Vec<Option<usize>> = vec![None; self.squares.len()];
+        while ! level.is_empty() && seen.len() < PROOF_SETS {
+            for set in level.iter() {
+                let group: Vec<&Hint> = set.iter().map(|&p| hints[p].as_ref().unwrap()).collect();
+                if let Some(v) = self.forced(s, &group, &mut var_of) {
+                    log!(LogLevel::Summary, "proof", s = s, value = v, digits = set.len(), sets = seen.len());
+                    return Some((v, set.clone()));
+                }
+            }
+
+            // one more digit sharing an unknown square with the set
+            let mut next: Vec<Vec<usize>> = Vec::new();
+            for set in level.iter() {
+                for &p in set.iter() {
+                    for &q in hints[p].as_ref().unwrap().squares.iter() {
+                        for &p2 in around[q].iter().filter(|p2| ! set.contains(p2)) {
+                            let mut bigger = set.clone();
+                            bigger.push(p2);
+                            bigger.sort();
+                            if seen.len() < PROOF_SETS && seen.insert(bigger.clone()) {
+                                next.push(bigger);
+                            }
+                        }
+                    }
+                }
+            }
+            level = next;
+        }
+        log!(LogLevel::Summary, "proof", s = s, found = false, sets = seen.len());
+        None
+    }
+
+    // the value of square s in every solution of the hints, None if they differ
+    fn forced(&self, s: usize, hints: &[&Hint], var_of: &mut [Option<usize>]) -> Option<i32> {
+        let mut vars: Vec<usize> = Vec::new();
+        for hint in hints.iter() {
+            for &q in hint.squares.iter() {
+                if self.squares[q].state == SquareState::Unknown && var_of[q].is_none() {
+                    var_of[q] = Some(vars.len());
+                    vars.push(q);
+                }
+            }
+        }
+        let target = var_of[s];
+        let mut search = self.new_search(hints, vars.len(), var_of);
+        let finished = search.run(0, &Limits::default(), 0);
+        for &q in vars.iter() {
+            var_of[q] = None;
+        }
+
+        let seen = search.seen[target?];
+        if finished && search.solutions > 0 && seen.count_ones() == 1 {
+            Some(self.range.0 + seen.trailing_zeros() as i32)
+        } else {
+            None
+        }
+    }
+}
+
+/* ------------------------------------------------------------- Impl: Board: parallel regions */
+// Unknown squares linked by no chain of hints never influence each other: every
+// rule works on one hint or two sharing a square, and their new hints stay on the
+// same squares. Known squares link nothing, the first rule drops them from hints.
+// Such regions are spread over boards of their own, one per thread,
+// and deduced there. On its own board a region pops its squares in the same
+// order as on the whole board (the stack only interleaves regions), so a complete
+// run ends with the same squares known as a single-threaded one.
+// Then each thread board is merged back as one Delta, in thread order, and solve()
+// finishes: a pass over the pushed squares finding nothing new, the search and
+// process_remaining(), which needs the whole board. Stats add up the work of the
+// thread boards and of this one, not the merging.
+// All threads and that solve() share one step count (and the deadline), so the
+// limits hold for the whole run. A region stopping makes it Incomplete, with
+// what every region found merged; the others stop on the same limits anyway,
+// but for Stop::Nodes, which is the region's own. What is found by then differs
+// from a single-threaded run stopped alike: regions take the steps at their own pace.
+impl Board {
+    fn solve_parallel(&mut self, limits: &Limits, threads: usize) -> Outcome {
+        let regions = self.regions();
+        log!(LogLevel::Summary, "regions", regions = regions.len(), threads = threads);
+        if threads < 2 || regions.len() < 2 {
+            return self.solve(limits);
+        }
+
+        // regions to threads, largest first to the least loaded
+        let mut order: Vec<usize> = (0..regions.len()).collect();
+        order.sort_by_key(|&r| cmp::Reverse(regions[r].1));
+        let mut buckets: Vec<(usize, Vec<usize>)> = vec![(0, Vec::new()); cmp::min(threads, regions.len())];
+        for r in order {
+            let bucket = buckets.iter_mut().min_by_key(|b| b.0).unwrap();
+            bucket.0 += regions[r].1;
+            bucket.1.extend_from_slice(&regions[r].0);
+        }
+        let boards: Vec<std::sync::Mutex<Board>> = buckets.iter_mut()
+            .map(|&mut (_, ref mut hints)| { hints.sort(); std::sync::Mutex::new(self.region_board(hints)) })
+            .collect();
+
+        let limits = Limits { spent: Some(Arc::new(AtomicU64::new(0))), ..limits.clone() };
+        let stops: Vec<Option<Stop>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = boards.iter()
+                .map(|board| { let limits = &limits; scope.spawn(move || board.lock().unwrap().deduce(limits)) })
+                .collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
+
+        for (board, (_, hints)) in boards.into_iter().zip(buckets.iter()) {
+            let board = board.into_inner().unwrap();
+            self.merge_region(&board, hints);
+        }
+        if let Some(stop) = stops.into_iter().flatten().next() {
+            self.stats.log();
+            return Outcome::Incomplete(stop);
+        }
+        self.solve(&limits)
+    }
+
+    // (hints, unknown squares count) of each region with hints, by their first hint
+    fn regions(&self) -> Vec<(Vec<usize>, usize)> {
+        fn root(parent: &mut [usize], s: usize) -> usize {
+            let mut r = s;
+            while parent[r] != r { r = parent[r]; }
+            let mut s = s;
+            while parent[s] != r { let next = parent[s]; parent[s] = r; s = next; }
+            r
+        }
+
+        // squares of a hint together, but known ones: they link nothing
+        // "liar one" groups together, see process_search()
+        let unknown = |s: &usize| self.squares[*s].state == SquareState::Unknown;
+        let mut parent: Vec<usize> = (0..self.squares.len()).collect();
+        let mut lie_first: Vec<Option<usize>> = Vec::new();  // by group
+        for hint in self.hints.iter().filter(|h| h.valid) {
+            let mut squares = hint.squares.iter().filter(|s| unknown(s));
+            if let Some(&first) = squares.next() {
+                for &s in squares {
+                    let (r, first) = (root(&mut parent, s), root(&mut parent, first));
+                    parent[r] = first;
+                }
+            }
+            if let Some(lie) = hint.lie {
+                let s = *hint.squares.iter().find(|s| unknown(s)).unwrap_or(&hint.squares[0]);
+                if lie >= lie_first.len() { lie_first.resize(lie+1, None); }
+                let first = *lie_first[lie].get_or_insert(s);
+                let (r, first) = (root(&mut parent, s), root(&mut parent, first));
+                parent[r] = first;
+            }
+        }
+
+        // hints with no unknown square by their first one
+        let mut region_of: Vec<Option<usize>> = vec![None; self.squares.len()];
+        let mut regions: Vec<(Vec<usize>, usize)> = Vec::new();
+        for (h, hint) in self.hints.iter().enumerate().filter(|&(_,h)| h.valid) {
+            let s = *hint.squares.iter().find(|s| unknown(s)).unwrap_or(&hint.squares[0]);
+            let r = root(&mut parent, s);
+            let region = match region_of[r] {
+                Some(region) => region,
+                None => { region_of[r] = Some(regions.len()); regions.push((Vec::new(), 0)); regions.len()-1 },
+            };
+            regions[region].0.push(h);
+        }
+        for s in (0..self.squares.len()).filter(|s| unknown(s)) {
+            if let Some(region) = region_of[root(&mut parent, s)] {
+                regions[region].1 += 1;
+            }
+        }
+        regions
+    }
+
+    // board with the given hints only (renumbered, in the same order),
+    // their squares and the part of the stack on them
+    fn region_board(&self, hints: &[usize]) -> Board {
+        let mut board = Board::new(self.w, self.h, self.d, self.bombs);
+        board.bombs_max = self.bombs_max;
+        board.range = self.range;
+        let mut new_h: Vec<Option<usize>> = vec![None; self.hints.len()];
+        for &h in hints.iter() {
+            new_h[h] = Some(board.hints.len());
+            board.hints.push(self.hints[h].clone());
+            board.stats.hint_added();
+        }
+        // known squares may be in hints of other regions too, those are left out
+        for hint in board.hints.iter() {
+            for &s in hint.squares.iter() {
+                if board.squares[s].hints.is_empty() {
+                    board.squares[s].state = self.squares[s].state;
+                    board.squares[s].hints = self.squares[s].hints.iter().filter_map(|&h| new_h[h]).collect();
+                }
+            }
+        }
+        for &s in self.stack.iter() {
+            if board.squares[s].hints.is_empty() { continue; }
+            board.squares[s].on_stack = true;
+            board.stack.push(s);
+        }
+        board
+    }
+
+    // what a region board found, as one Delta
+    fn merge_region(&mut self, board: &Board, hints: &[usize]) {
+        let mut delta = Delta::new();
+        delta.hints2rm.extend_from_slice(hints);
+        for hint in board.hints.iter().filter(|h| h.valid).rev() {  // apply_delta() adds them in reverse
+            delta.add_hint(hint.clone());
+        }
+        for (s, square) in board.squares.iter().enumerate() {
+            if self.squares[s].state == SquareState::Unknown {
+                if let Some(v) = square.state.value() { delta.set_square_value(s, v); }
+            }
+        }
+        // the hints moving back are no work, the region board counted what was
+        let counted = (self.stats.hints_added, self.stats.hints_removed);
+        self.update_delta(&mut delta);
+        (self.stats.hints_added, self.stats.hints_removed) = counted;
+        self.stats.add(&board.stats, hints.len());
+    }
+}
+
+/* ------------------------------------------------------------- Impl: Board snapshot */
+// Whole solver state between two stack pops, so process_stack() can be resumed
+// exactly where it was. Hint numbers are kept (removed hints stay as null)
+// as squares refer to hints by index.
+//   {"width","height","bombs","rows":[..],       <- like Board::to_json()
+//    "hints":[[bombs,[squares..]] | [bombs,[squares..],alt] | [bombs,[squares..],null,lie] | null, ..],
+//    "squares":[[hints..], ..], "stack":[squares..]}
+impl Board {
+    fn to_snapshot(&self) -> Json {
+        let ints = |v: &Vec<usize>| Json::Arr(v.iter().map(|&i| Json::Int(i as i64)).collect());
+        let hints = self.hints.iter()
+            .map(|hint| if hint.valid {
+                let mut pair = vec![Json::Int(hint.bombs as i64), ints(&hint.squares)];
+                pair.extend(hint.alt.map(|alt| Json::Int(alt as i64)));
+                if let Some(lie) = hint.lie {
+                    pair.extend(vec![Json::Null, Json::Int(lie as i64)]);  // never with alt
+                }
+                Json::Arr(pair)
+            } else {
+                Json::Null
+            })
+            .collect();
+
+        let mut json = self.to_json();
+        if let Json::Obj(ref mut pairs) = json {
+            pairs.push(("hints".to_string(), Json::Arr(hints)));
+            pairs.push(("squares".to_string(), Json::Arr(self.squares.iter().map(|sq| ints(&sq.hints)).collect())));
+            pairs.push(("stack".to_string(), ints(&self.stack)));
+        }
+        json
+    }
+
+    fn from_snapshot(json: &Json) -> Result<Board, String> {
+        let w = json.get_u32("width")?;
+        let h = json.get_u32("height")?;
+        let d = if json.get("depth").is_some() { json.get_u32("depth")? } else { 1 };
+        let (bombs, bombs_max) = json.get_total("bombs")?;
+        let mut board = Board::new(w, h, d, bombs);
+        board.bombs_max = bombs_max;
+        let n = board.squares.len();
+
+        // squares state, without making any hints
+        let rows = json.get_strings("rows")?;
+        if json.get("clues").is_some() {
+            board.clues = json.get_strings("clues")?;  // their hints are in "hints"
+        }
+        for range in board.clues.iter().filter_map(|clue| parse_range(clue)).collect::<Vec<_>>() {
+            board.range = range?;
+        }
+        check_board_rows(w, h*d, &rows)?;
+        for (s,token) in rows.iter().flat_map(|r| grid_tokens(r)).enumerate() {
+            let (digit, state) = parse_square(&token).unwrap();
+            board.digits[s] = digit;
+            board.squares[s].state = state;
+        }
+
+        for (h,hint) in json.get_arr("hints")?.iter().enumerate() {
+            board.hints.push(match *hint {
+                Json::Null => Hint::new(0, vec![]),
+                Json::Arr(ref pair) if pair.len() >= 2 && pair.len() <= 4 => {
+                    let squares = snapshot_indices(&pair[1], n)?;
+                    let lie = match pair.get(3) {
+                        Some(lie) => Some(lie.as_u32().ok_or(format!("hints: bad hint {}: {}", h, hint))? as usize),
+                        None => None,
+                    };
+                    let (lo, hi) = board.range;
+                    let n = squares.len() as i64;
+                    let fits = |count: &Json| match *count {  // a "liar one" hint may be the wrong one
+                        Json::Int(c) if n > 0 && ((c >= n*lo as i64 && c <= n*hi as i64) || lie.is_some()) => Some(c as i32),
+                        _ => None,
+                    };
+                    let mut hint = match (fits(&pair[0]), pair.get(2).filter(|&alt| *alt != Json::Null).map(fits)) {
+                        (Some(bombs), None) => Hint::new(bombs, squares),
+                        (Some(bombs), Some(Some(alt))) if bombs < alt && lie.is_none() => Hint::either(bombs, alt, squares).unwrap(),
+                        _ => return Err(format!("hints: bad hint {}: {}", h, hint)),
+                    };
+                    hint.lie = lie;
+                    hint
+                },
+                _ => return Err(format!("hints: [bombs,[squares..](,alt(,lie))] or null expected, got {}", hint)),
+            });
+        }
+
+        let squares = json.get_arr("squares")?;
+        if squares.len() != n {
+            return Err(format!("squares: {} expected, got {}", n, squares.len()));
+        }
+        for (s,hints) in squares.iter().enumerate() {
+            for h in snapshot_indices(hints, board.hints.len())? {
+                if ! board.hints[h].valid || board.hints[h].squares.binary_search(&s).is_err() {
+                    return Err(format!("squares: square {} is not in hint {}", s, h));
+                }
+                board.squares[s].add_hint(h);
+            }
+        }
+        for (h,hint) in board.hints.iter().enumerate() {
+            if let Some(s) = hint.squares.iter().find(|&&s| ! board.squares[s].hints.contains(&h)) {
+                return Err(format!("squares: hint {} missing from square {}", h, s));
+            }
+        }
+        board.stats.hints_alive = board.hints.iter().filter(|h| h.valid).count();
+        board.stats.hints_peak = board.stats.hints_alive;
+
+        for s in snapshot_indices(json.get("stack").unwrap_or(&Json::Null), n)? {
+            if board.squares[s].on_stack {
+                return Err(format!("stack: square {} given twice", s));
+            }
+            board.squares[s].on_stack = true;
+            board.stack.push(s);
+        }
+
+        Ok(board)
     }
+}
 
+// array of indices below limit
+fn snapshot_indices(json: &Json, limit: usize) -> Result<Vec<usize>, String> {
+    match *json {
+        Json::Arr(ref items) => items.iter()
+            .map(|i| match i.as_u32() {
+                Some(i) if (i as usize) < limit => Ok(i as usize),
+                _ => Err(format!("index below {} expected, got {}", limit, i)),
+            })
+            .collect(),
+        _ => Err(format!("array of indices expected, got {}", json)),
+    }
 }
 
 /* ------------------------------------------------------------- Impl: Display for ... */
 impl fmt::Display for Board {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        writeln!(f, "\n.--- Square bombs={} {}x{}", self.bombs, self.w, self.h)?;
+        writeln!(f, "\n.--- Square bombs={} {}", self.total(), self.size())?;
         
-        // squares
-        for y in 0..self.h {
-            write!(f, "| {:3}> ", self.xy2s(0,y))?;
-            for x in 0..self.w {
-                write!(f, "{}", self.squares[self.xy2s(x,y)])?;
+        // squares, layer by layer
+        for z in 0..self.d {
+            if self.d > 1 {
+                writeln!(f, "| z={}", z)?;
+            }
+            for y in 0..self.h {
+                write!(f, "| {:3}> ", self.xyz2s(0,y,z))?;
+                for x in 0..self.w {
+                    write!(f, "{}", self.squares[self.xyz2s(x,y,z)])?;
+                }
+                writeln!(f)?;
+            }
+        }
+
+        // hints
+        write!(f, "| Hints: ")?;
+        for (i,hint) in self.hints.iter().enumerate().filter(|&(_,h)| h.valid) {
+            write!(f, "{}:{} ", i, hint)?;
+        }
+        writeln!(f)?;
+
+        // stack
+        write!(f, "| Stack: ")?;
+        for s in self.stack.iter() {
+            write!(f, "{}, ", s)?;
+        }
+        writeln!(f, "\n`---\n")
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, ".--- Stats")?;
+        for (key, value) in self.fields() {
+            writeln!(f, "| {:14} {:>10}", key, value)?;
+        }
+        write!(f, "`---")
+    }
+}
+
+impl fmt::Display for Delta {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "\n.--- Delta")?;
+        
+        for hint in self.hints2add.iter() {
+            writeln!(f, "| +Hint: {}", hint)?;
+        }
+
+        for h in self.hints2rm.iter() {
+            writeln!(f, "| -Hint: {}", h)?;
+        }
+
+        for s in self.squares2bomb.iter() {
+            writeln!(f, "| Bomb: {}", s)?;
+        }
+
+        for s in self.squares2empty.iter() {
+            writeln!(f, "| Empty: {}", s)?;
+        }
+
+        for &(s, v) in self.squares2mines.iter() {
+            writeln!(f, "| Mines: {}={}", s, v)?;
+        }
+
+        writeln!(f, "`---")
+    }
+}
+impl fmt::Debug for Delta {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self, f)
+    }
+}
+
+impl fmt::Display for Hint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(")?;
+        if self.valid {
+            write!(f, "{}", self.bombs)?;
+            if let Some(alt) = self.alt {
+                write!(f, "|{}", alt)?;
+            }
+            if let Some(lie) = self.lie {
+                write!(f, "~{}", lie)?;
+            }
+            write!(f, "@")?;
+            for s in &self.squares {
+                write!(f, "{},", s)?;
             }
-            writeln!(f, "")?;
+        } else {
+            write!(f, "INVALID")?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let inner = match self.state {
+            SquareState::Bomb => "*".to_string(),
+            SquareState::Empty => ".".to_string(),
+            SquareState::Mines(v) => format!("{}", v),
+            SquareState::Unknown if self.hints.is_empty() => "?".to_string(),
+            SquareState::Unknown  => format!("{}", self.hints.len()),
+        };
+
+        if self.on_stack {
+            write!(f, "[{}]", inner)?;
+        } else {
+            write!(f, " {} ", inner)?;
+        }
+        Ok(())
+    }
+}
+
+/* ------------------------------------------------------------- Stepper: ANSI terminal viewer */
+// Walks the history of a solved board one Delta at a time.
+// Shown state is "just before the next Delta": the square being processed
+// is in reverse video, squares of the hints the Delta replaces are yellow.
+impl Board {
+    // last square taken from the stack
+    fn current_square(&self) -> Option<usize> {
+        self.history.done.iter().rev()
+            .filter_map(|(step,_)| match *step { Step::Pop(s) => Some(s), _ => None })
+            .next()
+    }
+
+    fn next_delta(&self) -> Option<&Delta> {
+        match self.history.undone.last() {
+            Some(Step::Delta(delta)) => Some(delta),
+            _ => None,
+        }
+    }
+
+    // (applied, all) deltas
+    fn delta_count(&self) -> (usize, usize) {
+        let is_delta = |step: &Step| matches!(*step, Step::Delta(_));
+        let done = self.history.done.iter().filter(|&(step,_)| is_delta(step)).count();
+        (done, done + self.history.undone.iter().filter(|&step| is_delta(step)).count())
+    }
+
+    // back to the first Delta
+    fn rewind(&mut self) {
+        self.undo_to(0);
+        while self.next_delta().is_none() && self.redo() {}
+    }
+
+    fn step_forward(&mut self) -> bool {
+        if ! self.redo() { return false; }
+        while self.next_delta().is_none() && self.redo() {}
+        true
+    }
+
+    fn step_back(&mut self) -> bool {
+        if self.delta_count().0 == 0 { return false; }
+        loop {
+            let was_delta = matches!(self.history.done.last(), Some(&(Step::Delta(_), _)));
+            self.undo();
+            if was_delta { break; }
+        }
+        while self.next_delta().is_none() && self.redo() {}
+        true
+    }
+
+    fn write_ansi<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let current = self.current_square();
+        let mut involved: Vec<usize> = Vec::new();
+        if let Some(delta) = self.next_delta() {
+            for h in delta.hints2rm.iter() { involved.extend_from_slice(&self.hints[*h].squares); }
+        }
+
+        let (done, all) = self.delta_count();
+        write!(out, "\x1b[2J\x1b[H")?;
+        writeln!(out, "Delta {}/{}  bombs={} {}", done, all, self.total(), self.size())?;
+        let tokens = self.to_tokens();
+        let width = tokens.iter().flat_map(|row| row.iter().map(|t| t.len())).max().unwrap_or(1);
+        for (r,row) in tokens.iter().enumerate() {
+            let (y, z) = (r as u32 % self.h, r as u32 / self.h);
+            if y == 0 && z > 0 { writeln!(out)?; }  // between layers
+            for (x,token) in row.iter().enumerate() {
+                let s = self.xyz2s(x as u32, y, z);
+                let colour = match token.as_str() {
+                    "1" => "34", "2" => "32", "3" => "31", "4" => "35",
+                    "5" => "33", "6" => "36", "7" => "37", "8" => "90",
+                    "*" => "1;31", "-" => "1;32", "?" => "90",
+                    _ if token.starts_with('=') => "1;35",
+                    _ => "0",
+                };
+                let background = if Some(s) == current { "7" } else if involved.contains(&s) { "43" } else { "49" };
+                let sep = if width > 1 && x > 0 { " " } else { "" };
+                write!(out, "{}\x1b[{};{}m{:>w$}\x1b[0m", sep, colour, background, token, w = width)?;
+            }
+            writeln!(out)?;
+        }
+
+        match (current, self.next_delta()) {
+            (Some(s), Some(delta)) => {
+                writeln!(out, "\nsquare {}", s)?;
+                for h in delta.hints2rm.iter() {
+                    writeln!(out, "  hint {}: {}", h, self.hints[*h])?;
+                }
+                write!(out, "{}", delta)?;
+            },
+            _ => writeln!(out, "\nend")?,
+        }
+        Ok(())
+    }
+}
+
+// keys are read from the terminal as stdin holds the puzzle
+fn run_stepper(board: &mut Board) -> io::Result<()> {
+    let mut tty = io::BufReader::new(std::fs::File::open("/dev/tty")?);
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    board.rewind();
+    loop {
+        board.write_ansi(&mut out)?;
+        write!(out, "[Enter] next  [b] back  [e] end  [s] start  [q] quit > ")?;
+        out.flush()?;
+
+        let mut line = String::new();
+        if tty.read_line(&mut line)? == 0 { break; }
+        match line.trim() {
+            "" | "n" => { board.step_forward(); },
+            "b" => { board.step_back(); },
+            "e" => while board.step_forward() {},
+            "s" => board.rewind(),
+            "q" => break,
+            _ => {},
+        }
+    }
+    Ok(())
+}
+
+/* ------------------------------------------------------------- Export: SVG */
+const SVG_CELL: u32 = 24;
+const SVG_HINT_COLOURS: [&str; 6] = ["#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b"];
+
+impl Board {
+    // hints=TRUE draws the outline of every valid hint, each one a bit inset
+    // 3D boards: layers one below the other
+    fn write_svg<W: Write>(&self, out: &mut W, hints: bool) -> io::Result<()> {
+        let c = SVG_CELL;
+        writeln!(out, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" font-family="monospace" font-size="{}">"#,
+                 self.w*c, self.h*self.d*c, c*2/3)?;
+
+        for (y,row) in self.to_tokens().iter().enumerate() {
+            for (x,token) in row.iter().enumerate() {
+                let (px, py) = (x as u32*c, y as u32*c);
+                let fill = match token.as_str() { "?" => "#bbbbbb", "*" => "#f4b6b6", "-" => "#c8ecc8", _ => "#eeeeee" };
+                writeln!(out, r##"<rect x="{}" y="{}" width="{}" height="{}" fill="{}" stroke="#888888"/>"##, px, py, c, c, fill)?;
+                match token.as_str() {
+                    "." | "?" | "-" => {},
+                    "*" => writeln!(out, r##"<circle cx="{}" cy="{}" r="{}" fill="#000000"/>"##, px + c/2, py + c/2, c/4)?,
+                    _ => {
+                        // digits, variant ones and "=N" mines too
+                        let colour = match token.parse::<i32>() {
+                            Ok(d) if (1..=8).contains(&d) => ["#0000ff", "#008000", "#ff0000", "#000080", "#800000", "#008080", "#000000", "#808080"]
+                                [d as usize - 1],
+                            Ok(_) => "#000000",
+                            Err(_) => "#c00000",
+                        };
+                        writeln!(out, r#"<text x="{}" y="{}" text-anchor="middle" fill="{}">{}</text>"#,
+                                 px + c/2, py + c*3/4, colour, token.trim_start_matches('='))?;
+                    },
+                }
+            }
+        }
+
+        if hints {
+            for (i,(_,hint)) in self.hints.iter().enumerate().filter(|&(_,h)| h.valid).enumerate() {
+                self.write_svg_outline(out, hint, SVG_HINT_COLOURS[i % SVG_HINT_COLOURS.len()], 2 + (i as u32 % 4)*2)?;
+            }
+        }
+        writeln!(out, "</svg>")
+    }
+
+    // border of the hint squares, as the edges not shared with another square of the hint
+    fn write_svg_outline<W: Write>(&self, out: &mut W, hint: &Hint, colour: &str, inset: u32) -> io::Result<()> {
+        let c = SVG_CELL;
+        // y counts rows through the layers, as in write_svg()
+        let inside = |x: i64, y: i64| x >= 0 && y >= 0 && x < self.w as i64 && y < (self.h*self.d) as i64
+            && hint.squares.binary_search(&((y as u32*self.w + x as u32) as usize)).is_ok();
+
+        write!(out, r#"<path fill="none" stroke="{}" stroke-width="2" d=""#, colour)?;
+        for &s in hint.squares.iter() {
+            let (x, y) = ((s as u32 % self.w) as i64, (s as u32 / self.w) as i64);
+            let (x0, y0) = (x as u32*c + inset, y as u32*c + inset);
+            let (x1, y1) = ((x as u32+1)*c - inset, (y as u32+1)*c - inset);
+            if ! inside(x, y-1) { write!(out, "M{},{}H{}", x0, y0, x1)?; }
+            if ! inside(x+1, y) { write!(out, "M{},{}V{}", x1, y0, y1)?; }
+            if ! inside(x, y+1) { write!(out, "M{},{}H{}", x0, y1, x1)?; }
+            if ! inside(x-1, y) { write!(out, "M{},{}V{}", x0, y0, y1)?; }
+        }
+        writeln!(out, r#""/>"#)
+    }
+
+    // dir/step-0000.svg before the first Delta, then one file per applied Delta
+    // returns number of files written
+    fn write_svg_steps(&mut self, dir: &str, hints: bool) -> io::Result<usize> {
+        std::fs::create_dir_all(dir)?;
+        self.rewind();
+        let mut n = 0;
+        loop {
+            let path = std::path::Path::new(dir).join(format!("step-{:04}.svg", n));
+            let mut file = io::BufWriter::new(std::fs::File::create(path)?);
+            self.write_svg(&mut file, hints)?;
+            n += 1;
+            if ! self.step_forward() { break; }
+        }
+        Ok(n)
+    }
+}
+
+/* ------------------------------------------------------------- Formats: mine layout */
+// Hidden truth of a board, as exchanged with other minesweeper tools:
+// - ASCII: one row per line, '*' mine, '.' no mine
+// - MBF (replay communities): u8 w, u8 h, u16be mines, then u8 x, u8 y per mine
+// - JSON: {"width":w, "height":h, "mines":[[x,y], ...]}
+#[derive(Clone)]
+struct Layout {
+    w: u32,
+    h: u32,
+    mines: Vec<bool>,  // by square number, like Board::squares
+}
+
+impl Layout {
+    fn new(w: u32, h: u32) -> Layout {
+        Layout { w, h, mines: vec![false; (w*h) as usize] }
+    }
+
+    fn xy2s(&self, x: u32, y: u32) -> usize {
+        assert!(x < self.w && y < self.h);
+        (self.w*y + x ) as usize
+    }
+
+    fn n_mines(&self) -> u32 {
+        self.mines.iter().filter(|&&m| m).count() as u32
+    }
+
+    // n mines on distinct squares, the same ones for the same seed
+    fn random(w: u32, h: u32, n: u32, seed: u64) -> Layout {
+        let mut layout = Layout::new(w, h);
+        let mut squares: Vec<usize> = (0..layout.mines.len()).collect();
+        let mut rng = Rng::new(seed);
+        for i in 0..cmp::min(n as usize, squares.len()) {
+            let j = i + rng.below((squares.len() - i) as u32) as usize;
+            squares.swap(i, j);
+            layout.mines[squares[i]] = true;
+        }
+        layout
+    }
+
+    fn from_ascii(text: &str) -> Result<Layout, String> {
+        let rows: Vec<String> = text.lines()
+            .map(|l| l.trim_end().to_string())
+            .filter(|l| !l.is_empty())
+            .collect();
+        if rows.is_empty() {
+            return Err("ascii: no rows".to_string());
+        }
+        let w = rows[0].len() as u32;
+        let h = rows.len() as u32;
+        check_grid(w, h, &rows, "*.")?;
+
+        let mut layout = Layout::new(w, h);
+        for (y,row) in rows.iter().enumerate() {
+            for (x,ch) in row.chars().enumerate() {
+                let s = layout.xy2s(x as u32, y as u32);
+                layout.mines[s] = ch == '*';
+            }
+        }
+        Ok(layout)
+    }
+
+    fn to_ascii(&self) -> String {
+        let mut text = String::with_capacity(((self.w+1)*self.h) as usize);
+        for y in 0..self.h {
+            for x in 0..self.w {
+                text.push(if self.mines[self.xy2s(x,y)] { '*' } else { '.' });
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    fn from_mbf(bytes: &[u8]) -> Result<Layout, String> {
+        if bytes.len() < 4 {
+            return Err(format!("mbf: header too short ({} bytes)", bytes.len()));
+        }
+        let w = bytes[0] as u32;
+        let h = bytes[1] as u32;
+        let n = ((bytes[2] as usize) << 8) | bytes[3] as usize;
+        if bytes.len() != 4 + 2*n {
+            return Err(format!("mbf: {} mines need {} bytes, got {}", n, 4 + 2*n, bytes.len()));
+        }
+
+        let mut layout = Layout::new(w, h);
+        for pair in bytes[4..].chunks(2) {
+            let (x, y) = (pair[0] as u32, pair[1] as u32);
+            if x >= w || y >= h {
+                return Err(format!("mbf: mine {} {} outside {}x{} board", x, y, w, h));
+            }
+            let s = layout.xy2s(x, y);
+            if layout.mines[s] {
+                return Err(format!("mbf: mine {} {} given twice", x, y));
+            }
+            layout.mines[s] = true;
+        }
+        Ok(layout)
+    }
+
+    // Err when a size or the mine count does not fit its bytes
+    fn to_mbf(&self) -> Result<Vec<u8>, String> {
+        let n = self.n_mines();
+        if self.w > 255 || self.h > 255 || n > 0xffff {
+            return Err(format!("mbf: {}x{} with {} mines, 255x255 and 65535 mines at most", self.w, self.h, n));
+        }
+        let mut bytes: Vec<u8> = vec![self.w as u8, self.h as u8, (n >> 8) as u8, n as u8];
+        for y in 0..self.h {
+            for x in 0..self.w {
+                if self.mines[self.xy2s(x,y)] {
+                    bytes.push(x as u8);
+                    bytes.push(y as u8);
+                }
+            }
+        }
+        Ok(bytes)
+    }
+
+    fn from_json(json: &Json) -> Result<Layout, String> {
+        let w = json.get_u32("width")?;
+        let h = json.get_u32("height")?;
+        let mut layout = Layout::new(w, h);
+        for mine in json.get_arr("mines")? {
+            let (x, y) = match *mine {
+                Json::Arr(ref xy) if xy.len() == 2 => match (xy[0].as_u32(), xy[1].as_u32()) {
+                    (Some(x), Some(y)) if x < w && y < h => (x, y),
+                    _ => return Err(format!("mines: bad coordinates {}", mine)),
+                },
+                _ => return Err(format!("mines: [x,y] expected, got {}", mine)),
+            };
+            let s = layout.xy2s(x, y);
+            layout.mines[s] = true;
+        }
+        Ok(layout)
+    }
+
+    fn to_json(&self) -> Json {
+        let mut mines: Vec<Json> = Vec::new();
+        for y in 0..self.h {
+            for x in 0..self.w {
+                if self.mines[self.xy2s(x,y)] {
+                    mines.push(Json::Arr(vec![Json::Int(x as i64), Json::Int(y as i64)]));
+                }
+            }
+        }
+        Json::Obj(vec![
+            ("width".to_string(), Json::Int(self.w as i64)),
+            ("height".to_string(), Json::Int(self.h as i64)),
+            ("mines".to_string(), Json::Arr(mines)),
+        ])
+    }
+
+    // number of mines around (x,y)
+    fn count(&self, x: u32, y: u32) -> u32 {
+        let mut n = 0;
+        for ny in y.saturating_sub(1)..cmp::min(y+2, self.h) {
+            for nx in x.saturating_sub(1)..cmp::min(x+2, self.w) {
+                if (nx,ny) != (x,y) && self.mines[self.xy2s(nx,ny)] { n += 1; }
+            }
+        }
+        n
+    }
+
+    // squares uncovered by a click on (x,y), flooding through zeros like the game does
+    fn open(&self, x: u32, y: u32, revealed: &mut [bool]) {
+        let mut stack: Vec<(u32,u32)> = vec![(x,y)];
+        while let Some((x,y)) = stack.pop() {
+            let s = self.xy2s(x,y);
+            if revealed[s] || self.mines[s] { continue; }
+            revealed[s] = true;
+            if self.count(x,y) > 0 { continue; }
+            for ny in y.saturating_sub(1)..cmp::min(y+2, self.h) {
+                for nx in x.saturating_sub(1)..cmp::min(x+2, self.w) {
+                    stack.push((nx,ny));
+                }
+            }
+        }
+    }
+
+    // squares opened by first_click()
+    fn opening(&self) -> Vec<bool> {
+        let mut revealed = vec![false; self.mines.len()];
+        if let Some((x,y)) = self.first_click() {
+            self.open(x, y, &mut revealed);
+        }
+        revealed
+    }
+
+    // the first zero square (or first safe one when there is no zero)
+    fn first_click(&self) -> Option<(u32,u32)> {
+        let mut first_safe: Option<(u32,u32)> = None;
+        for y in 0..self.h {
+            for x in 0..self.w {
+                if self.mines[self.xy2s(x,y)] { continue; }
+                if self.count(x,y) == 0 {
+                    return Some((x,y));
+                }
+                if first_safe.is_none() { first_safe = Some((x,y)); }
+            }
+        }
+        first_safe
+    }
+
+    // puzzle with the given squares revealed
+    fn to_board(&self, revealed: &[bool]) -> Board {
+        let mut rows: Vec<String> = Vec::with_capacity(self.h as usize);
+        for y in 0..self.h {
+            let mut row = String::with_capacity(self.w as usize);
+            for x in 0..self.w {
+                row.push(match (revealed[self.xy2s(x,y)], self.count(x,y)) {
+                    (false, _) => '?',
+                    (true, 0) => '.',
+                    (true, n) => std::char::from_digit(n, 10).unwrap(),
+                });
+            }
+            rows.push(row);
+        }
+        Board::from_rows(self.w, self.h, self.n_mines(), &rows)
+    }
+}
+
+// random layouts that come back from the same seed, see Layout::random()
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed.wrapping_mul(0x9e3779b97f4a7c15) | 1)
+    }
+
+    // xorshift64*
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    fn below(&mut self, n: u32) -> u32 {
+        (self.next() % n as u64) as u32
+    }
+
+    #[cfg(test)]
+    fn chance(&mut self, percent: u32) -> bool {
+        self.below(100) < percent
+    }
+}
+
+// every row w long and made of allowed characters
+fn check_grid(w: u32, h: u32, rows: &[String], allowed: &str) -> Result<(), String> {
+    if rows.len() != h as usize {
+        return Err(format!("{} rows expected, got {}", h, rows.len()));
+    }
+    for (y,row) in rows.iter().enumerate() {
+        if row.chars().count() != w as usize {
+            return Err(format!("row {}: {} squares expected, got {}", y, w, row.chars().count()));
+        }
+        if let Some((x,ch)) = row.chars().enumerate().find(|&(_,ch)| ! allowed.contains(ch)) {
+            return Err(format!("row {}: unexpected '{}' at {}", y, ch, x));
+        }
+    }
+    Ok(())
+}
+
+// Board::from_rows() rows: classic ones square per character, variant ones
+// square per space separated token
+fn check_board_rows(w: u32, h: u32, rows: &[String]) -> Result<(), String> {
+    if rows.len() != h as usize {
+        return Err(format!("{} rows expected, got {}", h, rows.len()));
+    }
+    for (y,row) in rows.iter().enumerate() {
+        let tokens = grid_tokens(row);
+        if tokens.len() != w as usize {
+            return Err(format!("row {}: {} squares expected, got {}", y, w, tokens.len()));
+        }
+        let spaced = row.contains(' ');
+        if let Some((x,token)) = tokens.iter().enumerate()
+            .find(|&(_,t)| parse_square(t).is_none() || ! (spaced || "?.12345678*-".contains(t.as_str()))) {
+            return Err(format!("row {}: unexpected '{}' at {}", y, token, x));
+        }
+    }
+    Ok(())
+}
+
+fn grid_tokens(row: &str) -> Vec<String> {
+    if row.contains(' ') {
+        row.split_whitespace().map(|t| t.to_string()).collect()
+    } else {
+        row.chars().map(|ch| ch.to_string()).collect()
+    }
+}
+
+// one square of a grid: (revealed digit, state)
+//   '?' unknown, '.' or digit revealed, '*' known bomb, '-' known empty,
+//   '=N' known to hold N mines (variants)
+fn parse_square(token: &str) -> Option<(Option<i32>, SquareState)> {
+    match token {
+        "?" => Some((None, SquareState::Unknown)),
+        "." => Some((Some(0), SquareState::Empty)),
+        "*" => Some((None, SquareState::Bomb)),
+        "-" => Some((None, SquareState::Empty)),
+        _ if token.starts_with('=') => token[1..].parse::<i32>().ok().map(|v| (None, SquareState::of(v))),
+        _ => token.parse::<i32>().ok().map(|digit| (Some(digit), SquareState::Empty)),
+    }
+}
+
+// bombs count of the input: "n", "LO-HI" when a range is known, '?' when nothing is
+// (LO, HI), (n, n) or (0, u32::MAX)
+fn parse_total(token: &str) -> Option<(u32, u32)> {
+    if token == "?" {
+        return Some((0, u32::MAX));
+    }
+    let total: Vec<u32> = token.splitn(2, '-').map(|n| n.parse::<u32>().ok()).collect::<Option<_>>()?;
+    match total.len() {
+        1 => Some((total[0], total[0])),
+        _ if total[0] <= total[1] && total[1] < u32::MAX => Some((total[0], total[1])),
+        _ => None,
+    }
+}
+
+// "mines LO HI" clue, None for other clues
+// at most 64 values: the search keeps those seen on a square in a u64
+fn parse_range(clue: &str) -> Option<Result<(i32, i32), String>> {
+    let words: Vec<&str> = clue.split_whitespace().collect();
+    if words.first() != Some(&"mines") {
+        return None;
+    }
+    let range: Vec<i32> = words[1..].iter().filter_map(|n| n.parse::<i32>().ok()).collect();
+    Some(match range.len() {
+        2 if words.len() == 3 && range[0] < range[1] && (range[1] as i64 - range[0] as i64) < 64 => Ok((range[0], range[1])),
+        _ => Err(format!("bad clue '{}'", clue)),
+    })
+}
+
+/* ------------------------------------------------------------- Formats: JSON */
+// just enough JSON for board files, no external crates on CodinGame
+#[derive(PartialEq,Debug,Clone)]
+enum Json {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Arr(Vec<Json>),
+    Obj(Vec<(String, Json)>),  // keeps key order
+}
+
+impl Json {
+    fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = JsonParser { bytes: text.as_bytes(), pos: 0 };
+        let json = parser.value()?;
+        parser.skip_ws();
+        if parser.pos != parser.bytes.len() {
+            return Err(format!("json: trailing characters at {}", parser.pos));
+        }
+        Ok(json)
+    }
+
+    fn get(&self, key: &str) -> Option<&Json> {
+        match *self {
+            Json::Obj(ref pairs) => pairs.iter().find(|(k,_)| k == key).map(|(_,v)| v),
+            _ => None,
+        }
+    }
+
+    fn as_u32(&self) -> Option<u32> {
+        match *self {
+            Json::Int(i) if i >= 0 && i <= u32::MAX as i64 => Some(i as u32),
+            _ => None,
+        }
+    }
+
+    fn get_u32(&self, key: &str) -> Result<u32, String> {
+        self.get(key).and_then(|v| v.as_u32()).ok_or(format!("{}: unsigned number expected", key))
+    }
+
+    // bombs total: a number, or a string for parse_total()
+    fn get_total(&self, key: &str) -> Result<(u32, u32), String> {
+        match self.get(key) {
+            Some(Json::Str(total)) => parse_total(total),
+            total => total.and_then(|v| v.as_u32()).map(|n| (n, n)),
+        }.ok_or(format!("{}: unsigned number, \"LO-HI\" or \"?\" expected", key))
+    }
+
+    fn get_strings(&self, key: &str) -> Result<Vec<String>, String> {
+        self.get_arr(key)?.iter()
+            .map(|item| match *item {
+                Json::Str(ref s) => Ok(s.clone()),
+                _ => Err(format!("{}: string expected", key)),
+            })
+            .collect()
+    }
+
+    fn get_arr(&self, key: &str) -> Result<&Vec<Json>, String> {
+        match self.get(key) {
+            Some(Json::Arr(items)) => Ok(items),
+            _ => Err(format!("{}: array expected", key)),
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> JsonParser<'a> {
+    fn skip_ws(&mut self) {
+        while self.pos < self.bytes.len() && (self.bytes[self.pos] as char).is_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_ws();
+        self.bytes.get(self.pos).cloned()
+    }
+
+    fn expect(&mut self, ch: u8) -> Result<(), String> {
+        if self.peek() == Some(ch) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("json: '{}' expected at {}", ch as char, self.pos))
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        if self.bytes[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            Ok(value)
+        } else {
+            Err(format!("json: unexpected token at {}", self.pos))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        match self.peek() {
+            Some(b'{') => {
+                self.pos += 1;
+                let mut pairs: Vec<(String, Json)> = Vec::new();
+                if self.peek() == Some(b'}') { self.pos += 1; return Ok(Json::Obj(pairs)); }
+                loop {
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    pairs.push((key, self.value()?));
+                    if self.peek() == Some(b',') { self.pos += 1; continue; }
+                    self.expect(b'}')?;
+                    return Ok(Json::Obj(pairs));
+                }
+            },
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items: Vec<Json> = Vec::new();
+                if self.peek() == Some(b']') { self.pos += 1; return Ok(Json::Arr(items)); }
+                loop {
+                    items.push(self.value()?);
+                    if self.peek() == Some(b',') { self.pos += 1; continue; }
+                    self.expect(b']')?;
+                    return Ok(Json::Arr(items));
+                }
+            },
+            Some(b'"') => Ok(Json::Str(self.string()?)),
+            Some(b't') => self.keyword("true", Json::Bool(true)),
+            Some(b'f') => self.keyword("false", Json::Bool(false)),
+            Some(b'n') => self.keyword("null", Json::Null),
+            Some(b'-') | Some(b'0'..=b'9') => self.number(),
+            Some(ch) => Err(format!("json: unexpected '{}' at {}", ch as char, self.pos)),
+            None => Err("json: unexpected end".to_string()),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        while self.pos < self.bytes.len() && b"+-0123456789.eE".contains(&self.bytes[self.pos]) {
+            self.pos += 1;
+        }
+        let text = std::str::from_utf8(&self.bytes[start..self.pos]).unwrap();
+        if let Ok(i) = text.parse::<i64>() {
+            return Ok(Json::Int(i));
+        }
+        text.parse::<f64>().map(Json::Float).map_err(|_| format!("json: bad number '{}' at {}", text, start))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut out: Vec<u8> = Vec::new();
+        loop {
+            let ch = match self.bytes.get(self.pos) {
+                Some(&ch) => ch,
+                None => return Err("json: unterminated string".to_string()),
+            };
+            self.pos += 1;
+            match ch {
+                b'"' => break,
+                b'\\' => {
+                    let esc = self.bytes.get(self.pos).cloned().unwrap_or(b' ');
+                    self.pos += 1;
+                    match esc {
+                        b'"' | b'\\' | b'/' => out.push(esc),
+                        b'b' => out.push(8),
+                        b'f' => out.push(12),
+                        b'n' => out.push(b'\n'),
+                        b'r' => out.push(b'\r'),
+                        b't' => out.push(b'\t'),
+                        b'u' => {
+                            let hex = self.bytes.get(self.pos..self.pos+4)
+                                .and_then(|h| std::str::from_utf8(h).ok())
+                                .and_then(|h| u32::from_str_radix(h, 16).ok())
+                                .and_then(std::char::from_u32)
+                                .ok_or(format!("json: bad \\u escape at {}", self.pos))?;
+                            self.pos += 4;
+                            let mut buf = [0u8; 4];
+                            out.extend_from_slice(hex.encode_utf8(&mut buf).as_bytes());
+                        },
+                        _ => return Err(format!("json: bad escape at {}", self.pos)),
+                    }
+                },
+                _ => out.push(ch),
+            }
+        }
+        String::from_utf8(out).map_err(|_| "json: bad utf-8 in string".to_string())
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Int(i) => write!(f, "{}", i),
+            Json::Float(x) => write!(f, "{}", x),
+            Json::Str(ref s) => {
+                write!(f, "\"")?;
+                for ch in s.chars() {
+                    match ch {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                write!(f, "\"")
+            },
+            Json::Arr(ref items) => {
+                write!(f, "[")?;
+                for (i,item) in items.iter().enumerate() {
+                    if i > 0 { write!(f, ",")?; }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            },
+            Json::Obj(ref pairs) => {
+                write!(f, "{{")?;
+                for (i,(k,v)) in pairs.iter().enumerate() {
+                    if i > 0 { write!(f, ",")?; }
+                    write!(f, "{}:{}", Json::Str(k.clone()), v)?;
+                }
+                write!(f, "}}")
+            },
+        }
+    }
+}
+
+/* ------------------------------------------------------------- Logging */
+// Goes to stderr. Level from --log or CG_LOG (default summary):
+//   quiet    nothing
+//   summary  start and result
+//   delta    every square, hint rule and applied Delta
+//   board    and whole board dumps after each Delta
+// --log-events or CG_LOG_FORMAT=events writes one "event=NAME key=value ..." line
+// per event instead, values quoted and escaped when needed, for tooling.
+#[derive(PartialEq,PartialOrd,Clone,Copy,Debug)]
+enum LogLevel {
+    Quiet,
+    Summary,
+    Delta,
+    Board,
+}
+
+static LOG_LEVEL: AtomicUsize = AtomicUsize::new(LogLevel::Summary as usize);
+static LOG_EVENTS: AtomicBool = AtomicBool::new(false);
+
+impl LogLevel {
+    fn parse(name: &str) -> Option<LogLevel> {
+        match name {
+            "quiet" => Some(LogLevel::Quiet),
+            "summary" => Some(LogLevel::Summary),
+            "delta" => Some(LogLevel::Delta),
+            "board" => Some(LogLevel::Board),
+            _ => None,
+        }
+    }
+}
+
+// arguments win over environment
+fn log_init(level: Option<&str>, events: bool) {
+    let env_level = env::var("CG_LOG").ok();
+    if let Some(name) = level.or(env_level.as_deref()) {
+        let level = LogLevel::parse(name).expect("log level: quiet, summary, delta or board");
+        LOG_LEVEL.store(level as usize, Ordering::Relaxed);
+    }
+    let env_events = env::var("CG_LOG_FORMAT").map(|f| f == "events").unwrap_or(false);
+    LOG_EVENTS.store(events || env_events, Ordering::Relaxed);
+}
+
+fn log_enabled(level: LogLevel) -> bool {
+    level != LogLevel::Quiet && level as usize <= LOG_LEVEL.load(Ordering::Relaxed)
+}
+
+fn log_line(event: &str, fields: &[(&str, String)], events: bool) -> String {
+    let mut line = if events { format!("event={}", event) } else { event.to_string() };
+    for &(key, ref value) in fields {
+        let plain = ! value.is_empty() && value.chars().all(|c| c.is_alphanumeric() || "_-.,@()".contains(c));
+        if ! events || plain {
+            line.push_str(&format!(" {}={}", key, value));
+        } else {
+            line.push_str(&format!(" {}={:?}", key, value));  // quoted, \n and " escaped
+        }
+    }
+    line
+}
+
+/* ------------------------------------------------------------- Options */
+// Command line, all optional (CodinGame runs the program without any):
+//   --in FORMAT     cg (default), json, or a mine layout: ascii, mbf, mines-json
+//                   layouts are turned into puzzles by a first click, see Layout::opening()
+//   --out FORMAT    bombs (default), cg, json, or the solved mine layout: ascii, mbf, mines-json,
+//                   or plan: clicks, flags and chords opening the empty squares, see Board::plan()
+//   --save-after N FILE    snapshot after N squares popped from the stack, then carry on
+//   --resume FILE          start from a snapshot instead of reading the input
+//   --step                 after solving, step through the deltas in the terminal
+//   --svg DIR              after solving, write DIR/step-NNNN.svg for every delta
+//   --svg-hints            outline valid hints in the SVG files
+//   --max-steps N          give up after N squares popped from the stack
+//   --time-limit MS        give up MS milliseconds after start, answering what is known
+//   --check FILE           don't solve, grade the bombs in FILE (print_bombs() format), see Checker
+//   --self-check           solve, then grade own answer
+//   --why X Y              don't solve, print the fewest digits forcing square X Y, see proof():
+//                          "X Y *" (or '-', "=v", '?' if not forced), then "x y digit" for each
+//   --play FILE            bot game on the input mine layout, replay log to FILE, see Bot: games and replays
+//   --random W H MINES SEED      play a random layout instead of reading one
+//   --replay FILE          solve the steps of a replay log again, print where the solver now differs
+//   --batch                solve a stream of puzzles, see Batch mode
+//   --threads N            batch worker threads, or threads solving independent regions, see solve_parallel()
+//   --log LEVEL            quiet, summary, delta or board, see Logging
+//   --log-events           log as parsable events
+struct Options {
+    input: String,
+    output: String,
+    save_after: Option<(usize, String)>,
+    resume: Option<String>,
+    step: bool,
+    svg: Option<String>,
+    svg_hints: bool,
+    log: Option<String>,
+    log_events: bool,
+    max_steps: Option<u64>,
+    time_limit: Option<Duration>,
+    check: Option<String>,
+    self_check: bool,
+    why: Option<(u32, u32)>,
+    play: Option<String>,
+    random: Option<(u32, u32, u32, u64)>,
+    replay: Option<String>,
+    batch: bool,
+    threads: usize,
+    started: Instant,
+}
+
+impl Options {
+    fn new() -> Options {
+        Options { input: "cg".to_string(), output: "bombs".to_string(),
+                  save_after: None, resume: None, step: false,
+                  svg: None, svg_hints: false, log: None, log_events: false,
+                  max_steps: None, time_limit: None, check: None, self_check: false,
+                  why: None, play: None, random: None, replay: None, batch: false, threads: 1,
+                  started: Instant::now() }
+    }
+
+    fn from_args() -> Options {
+        let mut options = Options::new();
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--in" => options.input = args.next().expect("--in needs a format"),
+                "--out" => options.output = args.next().expect("--out needs a format"),
+                "--save-after" => {
+                    let n = parse_input!(args.next().expect("--save-after needs a number"), usize);
+                    options.save_after = Some((n, args.next().expect("--save-after needs a file")));
+                },
+                "--resume" => options.resume = Some(args.next().expect("--resume needs a file")),
+                "--step" => options.step = true,
+                "--svg" => options.svg = Some(args.next().expect("--svg needs a directory")),
+                "--svg-hints" => options.svg_hints = true,
+                "--max-steps" => options.max_steps = Some(parse_input!(args.next().expect("--max-steps needs a number"), u64)),
+                "--time-limit" => {
+                    let ms = parse_input!(args.next().expect("--time-limit needs milliseconds"), u64);
+                    options.time_limit = Some(Duration::from_millis(ms));
+                },
+                "--check" => options.check = Some(args.next().expect("--check needs a file")),
+                "--self-check" => options.self_check = true,
+                "--why" => {
+                    let x = parse_input!(args.next().expect("--why needs X Y"), u32);
+                    options.why = Some((x, parse_input!(args.next().expect("--why needs X Y"), u32)));
+                },
+                "--play" => options.play = Some(args.next().expect("--play needs a file")),
+                "--random" => {
+                    let mut number = || parse_input!(args.next().expect("--random needs W H MINES SEED"), u64);
+                    let (w, h, mines) = (number() as u32, number() as u32, number() as u32);
+                    options.random = Some((w, h, mines, number()));
+                },
+                "--replay" => options.replay = Some(args.next().expect("--replay needs a file")),
+                "--batch" => options.batch = true,
+                "--threads" => options.threads = parse_input!(args.next().expect("--threads needs a number"), usize),
+                "--log" => options.log = Some(args.next().expect("--log needs a level")),
+                "--log-events" => options.log_events = true,
+                _ => panic!("unknown option {}", arg),
+            }
+        }
+        options
+    }
+
+    // time limit counted from start
+    fn limits(&self, start: Instant) -> Limits {
+        Limits { steps: self.max_steps, deadline: self.time_limit.map(|t| start + t), cancel: None, spent: None }
+    }
+}
+
+fn read_board(format: &str) -> Result<Board, String> {
+    match format {
+        "cg" => Ok(Board::from_stdin()),
+        "json" => {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text).map_err(|e| e.to_string())?;
+            Board::from_json(&Json::parse(&text)?)
+        },
+        _ => read_layout(format).map(|l| l.to_board(&l.opening())),
+    }
+}
+
+fn read_layout(format: &str) -> Result<Layout, String> {
+    let mut bytes: Vec<u8> = Vec::new();
+    io::stdin().read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+    let text = || String::from_utf8(bytes.clone()).map_err(|_| "input is not utf-8".to_string());
+    match format {
+        "ascii" => Layout::from_ascii(&text()?),
+        "mbf" => Layout::from_mbf(&bytes),
+        "mines-json" => Layout::from_json(&Json::parse(&text()?)?),
+        _ => Err(format!("unknown input format {}", format)),
+    }
+}
+
+// an incomplete board is still written, json tells it with "complete":false
+fn write_board(board: &Board, format: &str, outcome: Outcome) -> Result<(), String> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let result = match format {
+        "bombs" => { board.print_bombs(); Ok(()) },
+        "cg" => board.write_ascii(&mut out),
+        "json" => {
+            let mut json = board.to_json();
+            if let Json::Obj(ref mut pairs) = json {
+                pairs.push(("complete".to_string(), Json::Bool(outcome == Outcome::Complete)));
+            }
+            writeln!(out, "{}", json)
+        },
+        "ascii" => write!(out, "{}", board.to_layout()?.to_ascii()),
+        "mbf" => out.write_all(&board.to_layout()?.to_mbf()?),
+        "mines-json" => writeln!(out, "{}", board.to_layout()?.to_json()),
+        "plan" => {
+            if board.d > 1 || board.range != (0, 1) {
+                return Err("plan: classic flat boards only".to_string());
+            }
+            board.plan().iter().try_for_each(|action| writeln!(out, "{}", action))
+        },
+        _ => return Err(format!("unknown output format {}", format)),
+    };
+    result.map_err(|e| e.to_string())
+}
+
+/* ------------------------------------------------------------- Bot: move planner */
+// Turns a solved board into clicks for the game: every known empty square not
+// revealed yet gets opened. A chord on a revealed digit with all its bombs
+// flagged opens all its other neighbours at once, for the flags it needs and one
+// action; a square with no bombs around opens its neighbours by itself, as the
+// game does. Greedy: each step takes the action opening the most squares per
+// action spent (flags included), a click on ties. Nothing is flagged but
+// for chords. Only squares whose neighbours are all known count as a chord
+// centre or open more than themselves: the counts of the others are unknown.
+// A heuristic, not the fewest actions: on small solved boards it now and then
+// takes one more (plan_minimum() checks against a breadth-first search).
+#[derive(PartialEq,Debug,Clone,Copy)]
+enum Action {
+    Click(u32, u32),
+    Flag(u32, u32),
+    Chord(u32, u32),
+}
+
+// a candidate action: better opens more per cost, then a click, then a lower square
+#[derive(PartialEq,Eq,Clone,Copy)]
+struct Move {
+    opens: usize,
+    cost: usize,
+    chord: bool,
+    s: usize,
+}
+
+impl Ord for Move {
+    fn cmp(&self, other: &Move) -> cmp::Ordering {
+        (self.opens * other.cost).cmp(&(other.opens * self.cost))
+            .then(other.chord.cmp(&self.chord))
+            .then(other.s.cmp(&self.s))
+    }
+}
+
+impl PartialOrd for Move {
+    fn partial_cmp(&self, other: &Move) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Board {
+    // Moves wait in a heap with the value they had when pushed. Values only drop as
+    // squares open, so a popped move still worth its value is the best one; else it
+    // goes back with the new value. Flags make chords around cheaper and opened
+    // squares are new chord centres: those are pushed again.
+    fn plan(&self) -> Vec<Action> {
+        let n = self.squares.len();
+        let mut open: Vec<bool> = self.digits.iter().map(|d| d.is_some()).collect();
+        let mut flagged = vec![false; n];
+        let mut mark = vec![false; n];
+        let mut actions: Vec<Action> = Vec::new();
+
+        let mut heap: std::collections::BinaryHeap<Move> = std::collections::BinaryHeap::new();
+        for s in 0..n {
+            let chord = open[s];
+            if let Some((opened, cost)) = self.move_opens(s, chord, &open, &flagged, &mut mark) {
+                heap.push(Move { opens: opened.len(), cost, chord, s });
+            }
+        }
+
+        while let Some(best) = heap.pop() {
+            let (opened, cost) = match self.move_opens(best.s, best.chord, &open, &flagged, &mut mark) {
+                Some(value) => value,
+                None => continue,
+            };
+            if (opened.len(), cost) != (best.opens, best.cost) {
+                heap.push(Move { opens: opened.len(), cost, ..best });
+                continue;
+            }
+
+            let (x, y, _) = self.s2xyz(best.s);
+            let mut again: Vec<usize> = Vec::new();
+            if best.chord {
+                for q in self.neighbours(best.s) {
+                    if self.squares[q].state == SquareState::Bomb && ! flagged[q] {
+                        flagged[q] = true;
+                        let (qx, qy, _) = self.s2xyz(q);
+                        actions.push(Action::Flag(qx, qy));
+                        again.extend(self.neighbours(q).into_iter().filter(|&c| open[c]));
+                    }
+                }
+                actions.push(Action::Chord(x, y));
+            } else {
+                actions.push(Action::Click(x, y));
+            }
+            for q in opened {
+                open[q] = true;
+                again.push(q);
+            }
+            for c in again {
+                if let Some((opened, cost)) = self.move_opens(c, true, &open, &flagged, &mut mark) {
+                    heap.push(Move { opens: opened.len(), cost, chord: true, s: c });
+                }
+            }
+        }
+        log!(LogLevel::Summary, "plan", actions = actions.len(), flags = flagged.iter().filter(|&&f| f).count());
+        actions
+    }
+
+    // (squares opened, actions) of a click on s or a chord on it, None if it opens nothing
+    fn move_opens(&self, s: usize, chord: bool, open: &[bool], flagged: &[bool], mark: &mut [bool]) -> Option<(Vec<usize>, usize)> {
+        let target = |q: &usize| self.squares[*q].state == SquareState::Empty && ! open[*q];
+        let opened = if ! chord {
+            if ! target(&s) { return None; }
+            (self.opened_by(&[s], open, mark), 1)
+        } else {
+            if ! open[s] || self.known_count(s).is_none() { return None; }
+            let around = self.neighbours(s);
+            let starts: Vec<usize> = around.iter().cloned().filter(target).collect();
+            let flags = around.iter().filter(|&&q| self.squares[q].state == SquareState::Bomb && ! flagged[q]).count();
+            (self.opened_by(&starts, open, mark), 1 + flags)
+        };
+        if opened.0.is_empty() { None } else { Some(opened) }
+    }
+
+    // squares opened by revealing starts, through squares with no bombs around;
+    // mark is all false, and left so
+    fn opened_by(&self, starts: &[usize], open: &[bool], mark: &mut [bool]) -> Vec<usize> {
+        let mut opened: Vec<usize> = Vec::new();
+        for &s in starts {
+            if ! mark[s] { mark[s] = true; opened.push(s); }
+        }
+        let mut i = 0;
+        while i < opened.len() {
+            if self.known_count(opened[i]) == Some(0) {
+                for q in self.neighbours(opened[i]) {
+                    if ! open[q] && ! mark[q] { mark[q] = true; opened.push(q); }
+                }
+            }
+            i += 1;
+        }
+        for &s in opened.iter() {
+            mark[s] = false;
+        }
+        opened
+    }
+
+    fn neighbours(&self, s: usize) -> Vec<usize> {
+        let (x, y, z) = self.s2xyz(s);
+        self.bomb2hint(x, y, z, 0).squares
+    }
+
+    // bombs around s, None while some neighbour is unknown
+    fn known_count(&self, s: usize) -> Option<usize> {
+        let mut count = 0;
+        for q in self.neighbours(s) {
+            match self.squares[q].state {
+                SquareState::Unknown => return None,
+                SquareState::Bomb => count += 1,
+                _ => {},
+            }
+        }
+        Some(count)
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Action::Click(x, y) => write!(f, "click {} {}", x, y),
+            Action::Flag(x, y) => write!(f, "flag {} {}", x, y),
+            Action::Chord(x, y) => write!(f, "chord {} {}", x, y),
+        }
+    }
+}
+
+/* ------------------------------------------------------------- Bot: games and replays */
+// The bot plays a layout: solve the revealed part, plan(), carry the actions out,
+// and again until every empty square is open or the solver is stuck (no guessing).
+// A replay log keeps all of it, one JSON document:
+//   {"seed":n|null, "layout":{Layout::to_json()}, "start":"click x y",
+//    "steps":[{"t_ms":..,"safe":[[x,y],..],"bombs":[[x,y],..],"actions":["flag x y","chord x y",..]},..],
+//    "result":"won"|"stuck"|"lost: .."}
+// safe and bombs are what the solver knew at that step, t_ms counts from the start.
+// replay() walks the logged actions again and solves each step anew: steps where
+// the solver now decides (or plans) otherwise are reported.
+struct Game {
+    layout: Layout,
+    revealed: Vec<bool>,
+    flagged: Vec<bool>,
+}
+
+type Xys = Vec<(u32, u32)>;
+
+impl Game {
+    fn new(layout: Layout) -> Game {
+        let n = layout.mines.len();
+        Game { layout, revealed: vec![false; n], flagged: vec![false; n] }
+    }
+
+    // Err when a mine is hit, or a chord is made on a closed square or a wrong number of flags
+    fn apply(&mut self, action: Action) -> Result<(), String> {
+        let layout = &self.layout;
+        match action {
+            Action::Click(x, y) | Action::Flag(x, y) | Action::Chord(x, y) if x >= layout.w || y >= layout.h =>
+                return Err(format!("{}: outside the board", action)),
+            Action::Click(x, y) => {
+                if layout.mines[layout.xy2s(x, y)] { return Err(format!("{}: mine", action)); }
+                layout.open(x, y, &mut self.revealed);
+            },
+            Action::Flag(x, y) => self.flagged[layout.xy2s(x, y)] = true,
+            Action::Chord(x, y) => {
+                let around: Vec<(u32,u32)> = (y.saturating_sub(1)..cmp::min(y+2, layout.h))
+                    .flat_map(|ny| (x.saturating_sub(1)..cmp::min(x+2, layout.w)).map(move |nx| (nx, ny)))
+                    .filter(|&xy| xy != (x, y))
+                    .collect();
+                let flags = around.iter().filter(|&&(nx, ny)| self.flagged[layout.xy2s(nx, ny)]).count() as u32;
+                if ! self.revealed[layout.xy2s(x, y)] || flags != layout.count(x, y) {
+                    return Err(format!("{}: not a digit with its flags", action));
+                }
+                for (nx, ny) in around {
+                    let s = layout.xy2s(nx, ny);
+                    if self.flagged[s] { continue; }
+                    if layout.mines[s] { return Err(format!("{}: mine at {} {}", action, nx, ny)); }
+                    layout.open(nx, ny, &mut self.revealed);
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn won(&self) -> bool {
+        self.revealed.iter().zip(self.layout.mines.iter()).all(|(&open, &mine)| open || mine)
+    }
+
+    // (safe squares to open, bombs) known to the solver, and its plan
+    fn decide(&self) -> (Xys, Xys, Vec<Action>) {
+        let mut board = self.layout.to_board(&self.revealed);
+        board.solve(&Limits::default());
+        let safe = (0..board.squares.len())
+            .filter(|&s| board.squares[s].state == SquareState::Empty && ! self.revealed[s])
+            .map(|s| { let (x, y, _) = board.s2xyz(s); (x, y) })
+            .collect();
+        (safe, board.bombs_xy(), board.plan())
+    }
+
+    // plays until won or stuck
+    fn play(layout: Layout, seed: Option<u64>) -> Json {
+        let start = Instant::now();
+        let mut game = Game::new(layout);
+        let opening = game.layout.first_click().map(|(x, y)| Action::Click(x, y));
+        if let Some(action) = opening {
+            game.apply(action).unwrap();
+        }
+
+        let mut steps: Vec<Json> = Vec::new();
+        let mut result = "stuck".to_string();
+        loop {
+            if game.won() { result = "won".to_string(); break; }
+            let (safe, bombs, plan) = game.decide();
+            let t_ms = start.elapsed().as_secs() as i64 * 1000 + start.elapsed().subsec_millis() as i64;
+            steps.push(Json::Obj(vec![
+                ("t_ms".to_string(), Json::Int(t_ms)),
+                ("safe".to_string(), xy_json(&safe)),
+                ("bombs".to_string(), xy_json(&bombs)),
+                ("actions".to_string(), Json::Arr(plan.iter().map(|a| Json::Str(a.to_string())).collect())),
+            ]));
+            if plan.is_empty() { break; }
+            if let Err(e) = plan.iter().map(|&action| game.apply(action)).collect::<Result<Vec<_>, _>>() {
+                result = format!("lost: {}", e);
+                break;
+            }
+        }
+        log!(LogLevel::Summary, "played", steps = steps.len(), result = result);
+
+        Json::Obj(vec![
+            ("seed".to_string(), seed.map(|s| Json::Int(s as i64)).unwrap_or(Json::Null)),
+            ("layout".to_string(), game.layout.to_json()),
+            ("start".to_string(), opening.map(|a| Json::Str(a.to_string())).unwrap_or(Json::Null)),
+            ("steps".to_string(), Json::Arr(steps)),
+            ("result".to_string(), Json::Str(result)),
+        ])
+    }
+
+    // differences between the log and the current solver, "step N: ..." each
+    fn replay(log: &Json) -> Result<Vec<String>, String> {
+        let layout = Layout::from_json(log.get("layout").ok_or("layout expected".to_string())?)?;
+        let mut game = Game::new(layout);
+        if let Some(Json::Str(start)) = log.get("start") {
+            game.apply(Action::parse(start)?)?;
+        }
+
+        let mut diffs: Vec<String> = Vec::new();
+        for (i, step) in log.get_arr("steps")?.iter().enumerate() {
+            let (safe, bombs, plan) = game.decide();
+            let logged = step.get("actions").ok_or(format!("step {}: actions expected", i))?;
+            let actions: Vec<Action> = match *logged {
+                Json::Arr(ref actions) => actions.iter()
+                    .map(|a| match *a { Json::Str(ref a) => Action::parse(a), _ => Err(format!("step {}: bad action {}", i, a)) })
+                    .collect::<Result<_,_>>()?,
+                _ => return Err(format!("step {}: actions expected", i)),
+            };
+            for (key, now) in [("safe", &safe), ("bombs", &bombs)].iter() {
+                let was = step.get(key).cloned().unwrap_or(Json::Null);
+                if was != xy_json(now) {
+                    diffs.push(format!("step {}: {} was {}, now {}", i, key, was, xy_json(now)));
+                }
+            }
+            if plan != actions {
+                let plan: Vec<String> = plan.iter().map(|a| a.to_string()).collect();
+                diffs.push(format!("step {}: actions were {}, now {}", i, logged, Json::Arr(plan.into_iter().map(Json::Str).collect())));
+            }
+            for action in actions {
+                game.apply(action).map_err(|e| format!("step {}: {}", i, e))?;
+            }
+        }
+        Ok(diffs)
+    }
+}
+
+impl Action {
+    // as displayed: "click x y", "flag x y", "chord x y"
+    fn parse(text: &str) -> Result<Action, String> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let xy: Vec<u32> = words.iter().skip(1).filter_map(|n| n.parse::<u32>().ok()).collect();
+        match (words.first(), xy.len(), words.len()) {
+            (Some(&"click"), 2, 3) => Ok(Action::Click(xy[0], xy[1])),
+            (Some(&"flag"), 2, 3) => Ok(Action::Flag(xy[0], xy[1])),
+            (Some(&"chord"), 2, 3) => Ok(Action::Chord(xy[0], xy[1])),
+            _ => Err(format!("bad action '{}'", text)),
+        }
+    }
+}
+
+fn xy_json(xy: &[(u32,u32)]) -> Json {
+    Json::Arr(xy.iter().map(|&(x,y)| Json::Arr(vec![Json::Int(x as i64), Json::Int(y as i64)])).collect())
+}
+
+/* ------------------------------------------------------------- Checker */
+// Grades a proposed answer (print_bombs() format) against the puzzle digits.
+// Works on a freshly read Board or a solved one, the digits don't change.
+// Variant boards: "x y v" lines give v mines, graded against the "mines" range;
+// "x y" is one mine.
+#[derive(PartialEq,Debug)]
+enum Violation {
+    Outside(u32, u32),                         // bomb not on the board
+    Twice(u32, u32),                           // same bomb given again
+    Revealed(u32, u32),                        // bomb on a revealed square
+    Range { x: u32, y: u32, mines: i32 },              // count outside the "mines" range
+    Digit { x: u32, y: u32, digit: i32, bombs: i32 },  // digit not satisfied
+    Total { expected: String, bombs: i64 },  // as Board::total()
+    Clue { clue: String, bombs: i32 },                 // variant clue not satisfied
+    Lies { clue: String, wrong: usize },               // "liar one" clue without exactly one wrong digit
+}
+
+// "x y" or "x y v" lines: (x, y, mines)
+fn parse_bombs(text: &str) -> Result<Vec<(u32,u32,i32)>, String> {
+    let mut bombs: Vec<(u32,u32,i32)> = Vec::new();
+    for (i,line) in text.lines().enumerate().filter(|&(_,l)| ! l.trim().is_empty()) {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if tokens.len() != 2 && tokens.len() != 3 {
+            return Err(format!("line {}: \"x y\" or \"x y v\" expected, got '{}'", i+1, line));
+        }
+        let bad = |_| format!("line {}: bad bomb '{}'", i+1, line);
+        let (x, y) = (tokens[0].parse::<u32>().map_err(bad)?, tokens[1].parse::<u32>().map_err(bad)?);
+        let v = match tokens.get(2) { Some(v) => v.parse::<i32>().map_err(bad)?, None => 1 };
+        bombs.push((x, y, v));
+    }
+    Ok(bombs)
+}
+
+impl Board {
+    fn check_bombs(&self, bombs: &[(u32,u32,i32)]) -> Vec<Violation> {
+        let mut violations: Vec<Violation> = Vec::new();
+        let mut given = vec![false; self.squares.len()];
+        let mut mines = vec![0; self.squares.len()];
+        for &(x,y,v) in bombs.iter() {
+            if x >= self.w || y >= self.h {
+                violations.push(Violation::Outside(x, y));
+                continue;
+            }
+            let s = self.xy2s(x, y);
+            if given[s] {
+                violations.push(Violation::Twice(x, y));
+                continue;
+            } else if self.digits[s].is_some() {
+                violations.push(Violation::Revealed(x, y));
+            } else if v < self.range.0 || v > self.range.1 {
+                violations.push(Violation::Range { x, y, mines: v });
+            }
+            given[s] = true;
+            mines[s] = v;
+        }
+
+        let liar = self.clues.iter().any(|clue| clue == "liar");
+        let around = |s: usize| {
+            let (x, y, _) = self.s2xyz(s);
+            self.bomb2hint(x, y, 0, 0).squares.iter().map(|&s| mines[s]).sum::<i32>()
+        };
+        let lie_clues: Vec<&String> = self.clues.iter().filter(|clue| clue.starts_with("liar one ")).collect();
+        let mut lies: Vec<usize> = Vec::new();
+        for clue in lie_clues.iter() {
+            let squares = self.lie_squares(clue).unwrap();
+            let wrong = squares.iter().filter(|&&s| around(s) != self.digits[s].unwrap()).count();
+            if wrong != 1 {
+                violations.push(Violation::Lies { clue: clue.to_string(), wrong });
+            }
+            lies.extend(squares);
+        }
+        for y in 0..self.h {
+            for x in 0..self.w {
+                let s = self.xy2s(x, y);
+                if let Some(digit) = self.digits[s].filter(|_| ! lies.contains(&s)) {
+                    let around = around(s);
+                    if (liar && (around - digit).abs() != 1) || (! liar && around != digit) {
+                        violations.push(Violation::Digit { x, y, digit, bombs: around });
+                    }
+                }
+            }
+        }
+
+        for clue in self.clues.iter().filter(|clue| parse_range(clue).is_none() && *clue != "liar" && ! lie_clues.contains(clue)) {
+            let hint = self.parse_clue(clue).unwrap();
+            let bombs: i32 = hint.squares.iter().map(|&s| mines[s]).sum();
+            if bombs != hint.bombs && Some(bombs) != hint.alt {
+                violations.push(Violation::Clue { clue: clue.clone(), bombs });
+            }
+        }
+
+        let n: i64 = mines.iter().map(|&v| v as i64).sum();
+        if n < self.bombs as i64 || n > self.bombs_max as i64 {
+            violations.push(Violation::Total { expected: self.total(), bombs: n });
+        }
+        violations
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Violation::Outside(x, y) => write!(f, "{} {}: bomb outside the board", x, y),
+            Violation::Twice(x, y) => write!(f, "{} {}: bomb given twice", x, y),
+            Violation::Revealed(x, y) => write!(f, "{} {}: bomb on a revealed square", x, y),
+            Violation::Range { x, y, mines } => write!(f, "{} {}: {} mines, outside the mines range", x, y, mines),
+            Violation::Digit { x, y, digit, bombs } => write!(f, "{} {}: digit {} has {} bombs around", x, y, digit, bombs),
+            Violation::Total { ref expected, bombs } => write!(f, "total: {} bombs expected, {} given", expected, bombs),
+            Violation::Clue { ref clue, bombs } => write!(f, "clue '{}': {} bombs given", clue, bombs),
+            Violation::Lies { ref clue, wrong } => write!(f, "clue '{}': {} wrong digits given", clue, wrong),
+        }
+    }
+}
+
+/* ------------------------------------------------------------- Batch mode */
+// Input (--in): cg puzzles one after another (blank lines between are fine),
+// or json, one Board::to_json() document per line with an optional "id".
+// Output: one json line per puzzle in input order, whatever the number of threads:
+//   {"id":..,"ok":true,"complete":true,"bombs":[[x,y],..],"of":n,"time_us":..}   ([x,y,z] on 3D boards)
+//   ("of" is "LO-HI" or "?" when the total is not known)
+//   {"id":..,"ok":false,"error":"..","time_us":..}
+// A puzzle that can't be read or makes the solver panic fails alone.
+struct Job {
+    id: Json,                      // from input, or position in the stream from 1
+    input: Result<String, String>, // text of one puzzle, or why it could not be cut out
+}
+
+fn read_jobs<R: BufRead>(input: R, format: &str) -> Result<Vec<Job>, String> {
+    let lines: Vec<String> = input.lines().collect::<Result<_,_>>().map_err(|e| e.to_string())?;
+    let mut jobs: Vec<Job> = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        if lines[i].trim().is_empty() { i += 1; continue; }
+        let id = Json::Int(jobs.len() as i64 + 1);
+        match format {
+            "json" => {
+                let id = Json::parse(&lines[i]).ok()
+                    .and_then(|json| json.get("id").cloned())
+                    .unwrap_or(id);
+                jobs.push(Job { id, input: Ok(lines[i].clone()) });
+                i += 1;
+            },
+            "cg" => {
+                // header tells how many lines belong to the puzzle: h rows (h*d for 3D), and clues if any
+                let hwd: Vec<Option<usize>> = lines[i].split_whitespace().map(|n| n.parse::<usize>().ok()).collect();
+                let h = match hwd.len() {
+                    2 => hwd[0],
+                    3 => hwd[0].and_then(|h| hwd[2].map(|d| h*d)),
+                    _ => None,
+                };
+                let n_clues = lines.get(i+1)
+                    .map(|l| l.split_whitespace().nth(1).and_then(|n| n.parse::<usize>().ok()).unwrap_or(0));
+                match (h, n_clues) {
+                    (Some(h), Some(n)) if i + 2 + h + n <= lines.len() => {
+                        jobs.push(Job { id, input: Ok(lines[i..i+2+h+n].join("\n")) });
+                        i += 2 + h + n;
+                    },
+                    _ => {
+                        jobs.push(Job { id, input: Err(format!("line {}: bad header or truncated puzzle", i+1)) });
+                        i += 1;
+                    },
+                }
+            },
+            _ => return Err(format!("batch: cg or json input expected, got {}", format)),
+        }
+    }
+    Ok(jobs)
+}
+
+fn run_job(job: &Job, format: &str, options: &Options) -> Json {
+    let start = Instant::now();
+    let solved = job.input.clone().and_then(|text| {
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let mut board = match format {
+                "json" => Board::from_json(&Json::parse(&text)?)?,
+                _ => Board::parse_cg(&text)?,
+            };
+            let outcome = board.solve(&options.limits(start));
+            Ok((board.bombs_xyz(), board.d, board.total_json(), outcome))
+        })).unwrap_or_else(|panic| Err(
+            panic.downcast_ref::<&str>().map(|s| s.to_string())
+                .or(panic.downcast_ref::<String>().cloned())
+                .unwrap_or("solver panic".to_string())
+        ))
+    });
+    let time_us = start.elapsed().as_secs() as i64 * 1_000_000 + start.elapsed().subsec_micros() as i64;
+
+    let mut pairs = vec![("id".to_string(), job.id.clone())];
+    match solved {
+        Ok((bombs, d, of, outcome)) => {
+            let bombs = bombs.iter()
+                .map(|&(x,y,z)| Json::Arr(if d > 1 { vec![x, y, z] } else { vec![x, y] }.into_iter().map(|i| Json::Int(i as i64)).collect()))
+                .collect();
+            pairs.push(("ok".to_string(), Json::Bool(true)));
+            pairs.push(("complete".to_string(), Json::Bool(outcome == Outcome::Complete)));
+            pairs.push(("bombs".to_string(), Json::Arr(bombs)));
+            pairs.push(("of".to_string(), of));
+        },
+        Err(error) => {
+            pairs.push(("ok".to_string(), Json::Bool(false)));
+            pairs.push(("error".to_string(), Json::Str(error)));
+        },
+    }
+    pairs.push(("time_us".to_string(), Json::Int(time_us)));
+    Json::Obj(pairs)
+}
+
+// jobs handed out to threads one by one, results kept in input order
+fn run_batch(jobs: &[Job], options: &Options) -> Vec<Json> {
+    let next = AtomicUsize::new(0);
+    let results: std::sync::Mutex<Vec<Option<Json>>> = std::sync::Mutex::new(vec![None; jobs.len()]);
+    std::thread::scope(|scope| {
+        for _ in 0..cmp::max(options.threads, 1) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= jobs.len() { break; }
+                let result = run_job(&jobs[i], &options.input, options);
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+    results.into_inner().unwrap().into_iter().map(|r| r.unwrap()).collect()
+}
+
+/* ------------------------------------------------------------- main */
+#[cfg(not(test))]
+fn main() {
+    let options = Options::from_args();
+    log_init(options.log.as_deref(), options.log_events);
+    if options.batch {
+        let stdin = io::stdin();
+        let jobs = read_jobs(stdin.lock(), &options.input).unwrap();
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        for result in run_batch(&jobs, &options) {
+            writeln!(out, "{}", result).unwrap();
+        }
+        return;
+    }
+    if let Some(ref file) = options.replay {
+        let log = Json::parse(&std::fs::read_to_string(file).unwrap()).unwrap();
+        let diffs = Game::replay(&log).unwrap();
+        for diff in diffs.iter() {
+            println!("{}", diff);
+        }
+        if !diffs.is_empty() {
+            std::process::exit(1);
+        }
+        println!("OK");
+        return;
+    }
+    if let Some(ref file) = options.play {
+        let (layout, seed) = match options.random {
+            Some((w, h, mines, seed)) => (Layout::random(w, h, mines, seed), Some(seed)),
+            None => (read_layout(&options.input).unwrap(), None),
+        };
+        let log = Game::play(layout, seed);
+        std::fs::write(file, format!("{}\n", log)).unwrap();
+        if let Some(Json::Str(result)) = log.get("result") {
+            println!("{}", result);
+        }
+        return;
+    }
+    let mut board = match options.resume {
+        Some(ref file) => {
+            let text = std::fs::read_to_string(file).unwrap();
+            Board::from_snapshot(&Json::parse(&text).unwrap()).unwrap()
+        },
+        None => read_board(&options.input).unwrap(),
+    };
+    if options.step || options.svg.is_some() {
+        board.record_history();
+    }
+    if options.check.is_some() || options.self_check {
+        assert!(board.d == 1, "--check: flat boards only");
+        let bombs = match options.check {
+            Some(ref file) => parse_bombs(&std::fs::read_to_string(file).unwrap()).unwrap(),
+            None => { board.process_stack(); board.mines_xy() },
+        };
+        let violations = board.check_bombs(&bombs);
+        for violation in violations.iter() {
+            println!("{}", violation);
         }
-
-        // hints
-        write!(f, "| Hints: ")?;
-        for (i,hint) in self.hints.iter().enumerate().filter(|&(_,h)| h.valid) {
-            write!(f, "{}:{} ", i, hint)?;
+        if !violations.is_empty() {
+            std::process::exit(1);
         }
-        writeln!(f, "")?;
-
-        // stack
-        write!(f, "| Stack: ")?;
-        for s in self.stack.iter() {
-            write!(f, "{}, ", s)?;
+        println!("OK");
+        return;
+    }
+    if let Some((x, y)) = options.why {
+        assert!(board.d == 1 && x < board.w && y < board.h, "--why: square of a flat board");
+        match board.proof(board.xy2s(x, y)) {
+            Some((v, digits)) => {
+                let token = match SquareState::of(v) { SquareState::Bomb => "*".to_string(), SquareState::Empty => "-".to_string(), _ => format!("={}", v) };
+                println!("{} {} {}", x, y, token);
+                for p in digits {
+                    let (px, py, _) = board.s2xyz(p);
+                    println!("{} {} {}", px, py, board.digits[p].unwrap());
+                }
+            },
+            None => println!("{} {} ?", x, y),
         }
-        writeln!(f, "\n`---\n")
+        return;
+    }
+    if let Some((n, ref file)) = options.save_after {
+        board.process_stack_steps(n);
+        std::fs::write(file, format!("{}\n", board.to_snapshot())).unwrap();
+    }
+    let outcome = board.solve_parallel(&options.limits(options.started), options.threads);
+    if let Some(ref dir) = options.svg {
+        let n = board.write_svg_steps(dir, options.svg_hints).unwrap();
+        log!(LogLevel::Summary, "svg", files = n, dir = dir);
+    }
+    if options.step {
+        run_stepper(&mut board).unwrap();
+        return;
+    }
+    if let Err(e) = write_board(&board, &options.output, outcome) {
+        eprintln!("{}", e);
+        std::process::exit(1);
     }
 }
 
-impl fmt::Display for Delta {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        writeln!(f, "\n.--- Delta")?;
-        
-        for hint in self.hints2add.iter() {
-            writeln!(f, "| +Hint: {}", hint)?;
-        }
-
-        for h in self.hints2rm.iter() {
-            writeln!(f, "| -Hint: {}", h)?;
-        }
 
-        for s in self.squares2bomb.iter() {
-            writeln!(f, "| Bomb: {}", s)?;
-        }
+/* ------------------------------------------------------------- tests: random boards */
+// Property tests: a random mine layout, a random part of it revealed (and some
+// row totals as clues), solved. Every square the solver decides must match the
+// layout, and solve_parallel() must decide the same. A failing case is shrunk
+// to a small board before being reported. CG_TEST_SEED=n picks other boards.
+#[cfg(test)]
+#[derive(Clone)]
+struct Case {
+    layout: Layout,
+    revealed: Vec<bool>,
+    rows: Vec<u32>,  // with a "row Y N" clue
+}
 
-        for s in self.squares2empty.iter() {
-            writeln!(f, "| Empty: {}", s)?;
-        }
+#[cfg(test)]
+impl Case {
+    fn random(rng: &mut Rng) -> Case {
+        let (w, h) = (1 + rng.below(10), 1 + rng.below(10));
+        let (density, reveal) = (5 + rng.below(30), 20 + rng.below(75));
+        let mut layout = Layout::new(w, h);
+        let mines: Vec<bool> = (0..w*h).map(|_| rng.chance(density)).collect();
+        layout.mines = mines;
+        let revealed = layout.mines.iter().map(|&m| ! m && rng.chance(reveal)).collect();
+        let rows = (0..h).filter(|_| rng.chance(10)).collect();
+        Case { layout, revealed, rows }
+    }
 
-        writeln!(f, "`---")
+    fn board(&self) -> Board {
+        let mut board = self.layout.to_board(&self.revealed);
+        let clues: Vec<String> = self.rows.iter()
+            .map(|&y| format!("row {} {}", y, (0..self.layout.w).filter(|&x| self.layout.mines[self.layout.xy2s(x,y)]).count()))
+            .collect();
+        board.add_clues(&clues).unwrap();
+        board
     }
-}
-impl fmt::Debug for Delta {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Display::fmt(&self, f)
+
+    // None if the solver got it right
+    fn check(&self) -> Option<String> {
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let mut board = self.board();
+            board.process_stack();
+            for (s, square) in board.squares.iter().enumerate() {
+                match (square.state, self.layout.mines[s]) {
+                    (SquareState::Bomb, false) => return Some(format!("square {} is no bomb", s)),
+                    (SquareState::Empty, true) => return Some(format!("square {} is a bomb", s)),
+                    _ => {},
+                }
+            }
+            let mut parallel = self.board();
+            parallel.solve_parallel(&Limits::default(), 2);
+            if parallel.to_rows() != board.to_rows() {
+                return Some(format!("solve_parallel() differs: {:?}", parallel.to_rows()));
+            }
+            None
+        })).unwrap_or(Some("solver panic".to_string()))
     }
-}
 
-impl fmt::Display for Hint {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "(")?;
-        if self.valid {
-            write!(f, "{}@", self.bombs)?;
-            for s in &self.squares {
-                write!(f, "{},", s)?;
+    // columns x0.. and rows y0.. of the given size
+    fn crop(&self, x0: u32, y0: u32, w: u32, h: u32) -> Case {
+        let mut layout = Layout::new(w, h);
+        let mut revealed = vec![false; (w*h) as usize];
+        for y in 0..h {
+            for x in 0..w {
+                let (s, from) = (layout.xy2s(x, y), self.layout.xy2s(x0+x, y0+y));
+                layout.mines[s] = self.layout.mines[from];
+                revealed[s] = self.revealed[from];
             }
-        } else {
-            write!(f, "INVALID")?;
         }
-        write!(f, ")")
+        let rows = self.rows.iter().filter(|&&y| y >= y0 && y < y0+h).map(|&y| y-y0).collect();
+        Case { layout, revealed, rows }
     }
-}
 
-impl fmt::Display for Square {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let inner = match self.state {
-            SquareState::Bomb => format!("*"),
-            SquareState::Empty => format!("."),
-            SquareState::Unknown if self.hints.len() == 0 => format!("?"),
-            SquareState::Unknown  => format!("{}", self.hints.len()),
-        };
+    // smaller cases, biggest cuts first
+    fn smaller(&self) -> Vec<Case> {
+        let (w, h) = (self.layout.w, self.layout.h);
+        let mut cases: Vec<Case> = Vec::new();
+        if h > 1 { cases.push(self.crop(0, 1, w, h-1)); cases.push(self.crop(0, 0, w, h-1)); }
+        if w > 1 { cases.push(self.crop(1, 0, w-1, h)); cases.push(self.crop(0, 0, w-1, h)); }
+        for i in 0..self.rows.len() {
+            let mut case = self.clone();
+            case.rows.remove(i);
+            cases.push(case);
+        }
+        for s in 0..self.layout.mines.len() {
+            if self.layout.mines[s] {
+                let mut case = self.clone();
+                case.layout.mines[s] = false;
+                cases.push(case);
+            }
+            if self.revealed[s] {
+                let mut case = self.clone();
+                case.revealed[s] = false;
+                cases.push(case);
+            }
+        }
+        cases
+    }
 
-        if self.on_stack {
-            write!(f, "[{}]", inner)?;
-        } else {
-            write!(f, " {} ", inner)?;
+    // smallest case still failing, as far as one step at a time goes
+    fn shrink(self, fails: &dyn Fn(&Case) -> Option<String>) -> Case {
+        let mut case = self;
+        while let Some(smaller) = case.smaller().into_iter().find(|c| fails(c).is_some()) {
+            case = smaller;
         }
-        Ok(())
+        case
     }
 }
 
-/* ------------------------------------------------------------- main */
-#[cfg(not(test))]
-fn main() {
-    let mut board = Board::from_stdin();
-    board.process_stack();
-    board.print_bombs();
+#[cfg(test)]
+impl fmt::Display for Case {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut text: Vec<u8> = Vec::new();
+        self.board().write_ascii(&mut text).unwrap();
+        write!(f, "{}mines:\n{}", String::from_utf8(text).unwrap(), self.layout.to_ascii())
+    }
 }
 
-
 /* ------------------------------------------------------------- tests */
 #[test]
 fn process_h_0len() {
-    let board = Board { h:0, w:4, bombs:1, stack: vec![], squares: vec![
+    let board = Board { h:0, w:4, bombs:1, bombs_max:1, stack: vec![], digits: vec![], clues: vec![], history: History::new(), stats: Stats::default(), range: (0,1), d: 1, squares: vec![
         Square { on_stack: false, hints: vec![], state: SquareState::Unknown, }, // s:0
         Square { on_stack: false, hints: vec![], state: SquareState::Bomb, },    // s:1
         Square { on_stack: false, hints: vec![], state: SquareState::Unknown, }, // s:2
@@ -601,12 +3841,13 @@ fn process_h_0len() {
         hints2add: vec![],
         squares2bomb: vec![],
         squares2empty: vec![],
+        squares2mines: vec![],
     });
 }
 
 #[test]
 fn process_h_0delta() {
-    let board = Board { h:0, w:4, bombs:1, stack: vec![], squares: vec![
+    let board = Board { h:0, w:4, bombs:1, bombs_max:1, stack: vec![], digits: vec![], clues: vec![], history: History::new(), stats: Stats::default(), range: (0,1), d: 1, squares: vec![
         Square { on_stack: false, hints: vec![], state: SquareState::Unknown, }, // s:0
         Square { on_stack: false, hints: vec![], state: SquareState::Bomb, },    // s:1
         Square { on_stack: false, hints: vec![], state: SquareState::Unknown, }, // s:2
@@ -622,12 +3863,13 @@ fn process_h_0delta() {
         hints2add: vec![],
         squares2bomb: vec![],
         squares2empty: vec![],
+        squares2mines: vec![],
     });
 }
 
 #[test]
 fn process_h_known_bomb() {
-    let board = Board { h:0, w:4, bombs:1, stack: vec![], squares: vec![
+    let board = Board { h:0, w:4, bombs:1, bombs_max:1, stack: vec![], digits: vec![], clues: vec![], history: History::new(), stats: Stats::default(), range: (0,1), d: 1, squares: vec![
         Square { on_stack: false, hints: vec![], state: SquareState::Unknown, }, // s:0
         Square { on_stack: false, hints: vec![], state: SquareState::Bomb, },    // s:1
         Square { on_stack: false, hints: vec![], state: SquareState::Unknown, }, // s:2
@@ -644,12 +3886,13 @@ fn process_h_known_bomb() {
         hints2add: vec![ Hint::new(0, vec![0]) ],
         squares2bomb: vec![],
         squares2empty: vec![],
+        squares2mines: vec![],
     });
 }
 
 #[test]
 fn process_h_known_empty() {
-    let board = Board { h:0, w:4, bombs:1, stack: vec![], squares: vec![
+    let board = Board { h:0, w:4, bombs:1, bombs_max:1, stack: vec![], digits: vec![], clues: vec![], history: History::new(), stats: Stats::default(), range: (0,1), d: 1, squares: vec![
         Square { on_stack: false, hints: vec![], state: SquareState::Unknown, }, // s:0
         Square { on_stack: false, hints: vec![], state: SquareState::Unknown, }, // s:1
         Square { on_stack: false, hints: vec![], state: SquareState::Empty, },   // s:2
@@ -666,12 +3909,13 @@ fn process_h_known_empty() {
         hints2add: vec![ Hint::new(2, vec![1,3]) ],
         squares2bomb: vec![],
         squares2empty: vec![],
+        squares2mines: vec![],
     });
 }
 
 #[test]
 fn process_h_known_bomb_empty() {
-    let board = Board { h:0, w:4, bombs:1, stack: vec![], squares: vec![
+    let board = Board { h:0, w:4, bombs:1, bombs_max:1, stack: vec![], digits: vec![], clues: vec![], history: History::new(), stats: Stats::default(), range: (0,1), d: 1, squares: vec![
         Square { on_stack: false, hints: vec![], state: SquareState::Unknown, }, // s:0
         Square { on_stack: false, hints: vec![], state: SquareState::Bomb, },    // s:1
         Square { on_stack: false, hints: vec![], state: SquareState::Empty, },   // s:2
@@ -688,12 +3932,13 @@ fn process_h_known_bomb_empty() {
         hints2add: vec![ Hint::new(1, vec![0,3]) ],
         squares2bomb: vec![],
         squares2empty: vec![],
+        squares2mines: vec![],
     });
 }
 
 #[test]
 fn process_h_known_all() {
-    let board = Board { h:0, w:4, bombs:2, stack: vec![], squares: vec![
+    let board = Board { h:0, w:4, bombs:2, bombs_max:2, stack: vec![], digits: vec![], clues: vec![], history: History::new(), stats: Stats::default(), range: (0,1), d: 1, squares: vec![
         Square { on_stack: false, hints: vec![], state: SquareState::Empty, },   // s:0
         Square { on_stack: false, hints: vec![], state: SquareState::Bomb, },    // s:1
         Square { on_stack: false, hints: vec![], state: SquareState::Empty, },   // s:2
@@ -710,12 +3955,13 @@ fn process_h_known_all() {
         hints2add: vec![],  // empty vector becames invalid, so it was not added to delta
         squares2bomb: vec![],
         squares2empty: vec![],
+        squares2mines: vec![],
     });
 }
 
 #[test]
 fn process_h_all_bombs() {
-    let board = Board { h:0, w:4, bombs:1, stack: vec![], squares: vec![
+    let board = Board { h:0, w:4, bombs:1, bombs_max:1, stack: vec![], digits: vec![], clues: vec![], history: History::new(), stats: Stats::default(), range: (0,1), d: 1, squares: vec![
         Square { on_stack: false, hints: vec![], state: SquareState::Unknown, }, // s:0
         Square { on_stack: false, hints: vec![], state: SquareState::Bomb, },    // s:1
         Square { on_stack: false, hints: vec![], state: SquareState::Bomb, },    // s:2
@@ -732,12 +3978,13 @@ fn process_h_all_bombs() {
         hints2add: vec![],
         squares2bomb: vec![0,3],
         squares2empty: vec![],
+        squares2mines: vec![],
     });
 }
 
 #[test]
 fn process_h_all_empty() {
-    let board = Board { h:0, w:4, bombs:1, stack: vec![], squares: vec![
+    let board = Board { h:0, w:4, bombs:1, bombs_max:1, stack: vec![], digits: vec![], clues: vec![], history: History::new(), stats: Stats::default(), range: (0,1), d: 1, squares: vec![
         Square { on_stack: false, hints: vec![], state: SquareState::Unknown, }, // s:0
         Square { on_stack: false, hints: vec![], state: SquareState::Empty, },   // s:1
         Square { on_stack: false, hints: vec![], state: SquareState::Empty, },   // s:2
@@ -754,12 +4001,13 @@ fn process_h_all_empty() {
         hints2add: vec![],
         squares2bomb: vec![],
         squares2empty: vec![0,3],
+        squares2mines: vec![],
     });
 }
 
 #[test]
 fn process_p_inside() {
-    let board = Board { h:0, w:4, bombs:1, stack: vec![], squares: vec![
+    let board = Board { h:0, w:4, bombs:1, bombs_max:1, stack: vec![], digits: vec![], clues: vec![], history: History::new(), stats: Stats::default(), range: (0,1), d: 1, squares: vec![
         Square { on_stack: false, hints: vec![], state: SquareState::Unknown, }, // s:0
         Square { on_stack: false, hints: vec![], state: SquareState::Empty, },   // s:1
         Square { on_stack: false, hints: vec![], state: SquareState::Empty, },   // s:2
@@ -777,12 +4025,13 @@ fn process_p_inside() {
         hints2add: vec![ Hint::new(2, vec![1,3]) ],
         squares2bomb: vec![],
         squares2empty: vec![],
+        squares2mines: vec![],
     });
 }
 
 #[test]
 fn process_p_intersect() {
-    let board = Board { h:0, w:4, bombs:1, stack: vec![], squares: vec![
+    let board = Board { h:0, w:4, bombs:1, bombs_max:1, stack: vec![], digits: vec![], clues: vec![], history: History::new(), stats: Stats::default(), range: (0,1), d: 1, squares: vec![
         Square { on_stack: false, hints: vec![], state: SquareState::Unknown, }, // s:0
         Square { on_stack: false, hints: vec![], state: SquareState::Empty, },   // s:1
         Square { on_stack: false, hints: vec![], state: SquareState::Empty, },   // s:2
@@ -802,6 +4051,7 @@ fn process_p_intersect() {
         hints2add: vec![ Hint::new(1, vec![3,4]), Hint::new(1, vec![5]), Hint::new(0, vec![0,1,2]),], 
         squares2bomb: vec![],
         squares2empty: vec![],
+        squares2mines: vec![],
     });
 }
 
@@ -815,3 +4065,657 @@ fn hint_compare() {
 }
 
 
+
+#[test]
+fn board_rows_roundtrip() {
+    let text = "3 4\n2\n?1..\n?-*1\n.11?\n";
+    let board = Board::from_reader(&mut text.as_bytes());
+    assert_eq!(board.to_rows(), vec!["?1..", "?-*1", ".11?"]);
+
+    let mut out: Vec<u8> = Vec::new();
+    board.write_ascii(&mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), text);
+}
+
+#[test]
+fn board_json_roundtrip() {
+    let json = Json::parse(r#"{ "width": 3, "height": 2, "bombs": 1, "rows": ["?1.", "?1."] }"#).unwrap();
+    let board = Board::from_json(&json).unwrap();
+    assert_eq!(board.hints.len(), 2);
+    assert_eq!(board.to_json(), json);
+
+    let bad = Json::parse(r#"{"width":3, "height":2, "bombs":1, "rows":["?1.", "?x."]}"#).unwrap();
+    assert!(Board::from_json(&bad).is_err());
+}
+
+#[test]
+fn layout_formats() {
+    let layout = Layout::from_ascii("*..\n..*\n").unwrap();
+    assert_eq!(layout.to_mbf(), Ok(vec![3, 2, 0, 2, 0, 0, 2, 1]));
+    assert_eq!(Layout::from_mbf(&layout.to_mbf().unwrap()).unwrap().to_ascii(), "*..\n..*\n");
+    assert!(Layout::new(256, 1).to_mbf().is_err());
+    assert!(Layout::new(1, 300).to_mbf().is_err());
+    assert_eq!(Layout::from_json(&layout.to_json()).unwrap().mines, layout.mines);
+    assert!(Layout::from_mbf(&[3, 2, 0, 1, 3, 0]).is_err()); // outside
+    assert!(Layout::from_mbf(&[3, 2, 0, 2, 0, 0]).is_err()); // truncated
+}
+
+#[test]
+fn layout_opening() {
+    let layout = Layout::from_ascii("*...\n....\n....\n").unwrap();
+    let board = layout.to_board(&layout.opening());
+    assert_eq!(board.bombs, 1);
+    assert_eq!(board.to_rows(), vec!["?1..", "11..", "...."]);
+}
+
+#[test]
+fn snapshot_resume() {
+    let text = "5 5\n3\n??1..\n??1..\n11222\n..1??\n..1??\n";
+    let mut board = Board::from_reader(&mut text.as_bytes());
+    let mut resumed = Board::from_reader(&mut text.as_bytes());
+
+    assert!(resumed.process_stack_steps(4));
+    let snapshot = resumed.to_snapshot();
+    let mut resumed = Board::from_snapshot(&Json::parse(&snapshot.to_string()).unwrap()).unwrap();
+    assert_eq!(resumed.to_snapshot(), snapshot);
+
+    board.process_stack();
+    resumed.process_stack();
+    assert_eq!(resumed.to_snapshot(), board.to_snapshot());
+}
+
+#[test]
+fn snapshot_inconsistent() {
+    let json = r#"{"width":2,"height":1,"bombs":1,"rows":["1?"],"hints":[[1,[1]]],"squares":[[],[0]],"stack":[1]}"#;
+    assert!(Board::from_snapshot(&Json::parse(json).unwrap()).is_ok());
+    let json = r#"{"width":2,"height":1,"bombs":1,"rows":["1?"],"hints":[[1,[1]]],"squares":[[0],[0]],"stack":[1]}"#;
+    assert!(Board::from_snapshot(&Json::parse(json).unwrap()).is_err());
+    let json = r#"{"width":2,"height":1,"bombs":1,"rows":["1?"],"hints":[[1,[1]]],"squares":[[],[0]],"stack":[1,1]}"#;
+    assert!(Board::from_snapshot(&Json::parse(json).unwrap()).is_err());
+}
+
+#[test]
+fn undo_redo() {
+    let text = "9 9\n12\n?1....1??\n?2....12?\n?1.....11\n11.......\n12221..11\n????2112?\n????21???\n????32???\n????2????\n";
+    let mut board = Board::from_reader(&mut text.as_bytes());
+    board.record_history();
+    let start = board.to_snapshot();
+
+    board.process_stack_steps(10);
+    let (middle, checkpoint) = (board.to_snapshot(), board.checkpoint());
+    board.process_stack();
+    let end = board.to_snapshot();
+
+    board.undo_to(checkpoint);
+    assert_eq!(board.to_snapshot(), middle);
+    board.undo_to(0);
+    assert_eq!(board.to_snapshot(), start);
+    assert!(! board.undo());
+
+    while board.redo() {}
+    assert_eq!(board.to_snapshot(), end);
+
+    // new deduction after undo drops the redo list
+    board.undo_to(checkpoint);
+    board.process_stack();
+    assert_eq!(board.to_snapshot(), end);
+    assert!(! board.redo());
+}
+
+#[test]
+fn history_off() {
+    // solving alone keeps no history
+    let text = "5 5\n3\n??1..\n??1..\n11222\n..1??\n..1??\n";
+    let mut board = Board::from_reader(&mut text.as_bytes());
+    board.process_stack();
+    assert!(board.stats.deltas() > 0);
+    assert_eq!((board.checkpoint(), board.delta_count()), (0, (0, 0)));
+    assert!(! board.undo());
+}
+
+#[test]
+fn stepper_walk() {
+    let text = "5 5\n3\n??1..\n??1..\n11222\n..1??\n..1??\n";
+    let mut board = Board::from_reader(&mut text.as_bytes());
+    board.record_history();
+    board.process_stack();
+    let end = board.to_snapshot();
+    let (_, all) = board.delta_count();
+    assert_eq!(board.delta_count(), (all, all));
+
+    board.rewind();
+    assert_eq!(board.delta_count(), (0, all));
+    assert!(board.next_delta().is_some() && board.current_square().is_some());
+    assert!(! board.step_back());
+
+    assert!(board.step_forward());
+    let second = board.to_snapshot();
+    assert!(board.step_forward());
+    assert!(board.step_back());
+    assert_eq!(board.to_snapshot(), second);
+    assert_eq!(board.delta_count(), (1, all));
+
+    let mut screen: Vec<u8> = Vec::new();
+    board.write_ansi(&mut screen).unwrap();
+    assert!(String::from_utf8(screen).unwrap().starts_with(&format!("\x1b[2J\x1b[HDelta 1/{}", all)));
+
+    while board.step_forward() {}
+    assert_eq!(board.to_snapshot(), end);
+}
+
+#[test]
+fn svg_export() {
+    let text = "2 3\n1\n1?.\n11.\n";
+    let mut board = Board::from_reader(&mut text.as_bytes());
+
+    let mut svg: Vec<u8> = Vec::new();
+    board.write_svg(&mut svg, true).unwrap();
+    let svg = String::from_utf8(svg).unwrap();
+    assert_eq!(svg.matches("<rect").count(), 6);
+    assert_eq!(svg.matches("<path").count(), board.hints.iter().filter(|h| h.valid).count());
+
+    board.record_history();
+    board.process_stack();
+    let dir = env::temp_dir().join(format!("minesweeper-svg-{}", std::process::id()));
+    let n = board.write_svg_steps(dir.to_str().unwrap(), false).unwrap();
+    assert_eq!(n, board.delta_count().1 + 1);
+    let last = std::fs::read_to_string(dir.join(format!("step-{:04}.svg", n-1))).unwrap();
+    assert_eq!(last.matches("<circle").count(), 1);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn log_format() {
+    let fields = vec![("s", "23".to_string()), ("hint", "(1@18,23,)".to_string()), ("board", "\n| 0> ?\n".to_string())];
+    assert_eq!(log_line("square", &fields, false), "square s=23 hint=(1@18,23,) board=\n| 0> ?\n");
+    assert_eq!(log_line("square", &fields, true), r#"event=square s=23 hint=(1@18,23,) board="\n| 0> ?\n""#);
+    assert!(LogLevel::Summary < LogLevel::Board);
+    assert_eq!(LogLevel::parse("delta"), Some(LogLevel::Delta));
+}
+
+#[test]
+fn stats_count() {
+    let text = "9 9\n12\n?1....1??\n?2....12?\n?1.....11\n11.......\n12221..11\n????2112?\n????21???\n????32???\n????2????\n";
+    let mut board = Board::from_reader(&mut text.as_bytes());
+    let parsed = board.hints.len();
+    board.record_history();
+    board.process_stack();
+
+    // every delta comes from exactly one rule
+    assert_eq!(board.stats.deltas() as usize, board.delta_count().1);
+    assert!(board.stats.pops > 0);
+    assert!(board.stats.hints_peak >= parsed);
+    assert_eq!(board.stats.hints_added - board.stats.hints_removed, board.hints.iter().filter(|h| h.valid).count() as u64);
+    assert_eq!(board.stats.hints_alive as u64, board.stats.hints_added - board.stats.hints_removed);
+}
+
+#[test]
+fn solve_limits() {
+    let text = "9 9\n12\n?1....1??\n?2....12?\n?1.....11\n11.......\n12221..11\n????2112?\n????21???\n????32???\n????2????\n";
+    let mut board = Board::from_reader(&mut text.as_bytes());
+    board.process_stack();
+
+    let mut limited = Board::from_reader(&mut text.as_bytes());
+    let limits = Limits { steps: Some(20), ..Limits::default() };
+    assert_eq!(limited.solve(&limits), Outcome::Incomplete(Stop::Steps));
+    assert_eq!(limited.stats.pops, 20);
+    assert_eq!(limited.solve(&Limits::default()), Outcome::Complete);
+    assert_eq!(limited.to_snapshot(), board.to_snapshot());
+
+    let cancel = Arc::new(AtomicBool::new(true));
+    let limits = Limits { cancel: Some(cancel.clone()), ..Limits::default() };
+    let mut cancelled = Board::from_reader(&mut text.as_bytes());
+    assert_eq!(cancelled.solve(&limits), Outcome::Incomplete(Stop::Cancelled));
+    assert_eq!(cancelled.stats.pops, 0);
+
+    let limits = Limits { deadline: Some(Instant::now()), ..Limits::default() };
+    assert_eq!(cancelled.solve(&limits), Outcome::Incomplete(Stop::Deadline));
+
+    // one budget for all region threads
+    let text = format!("9 19\n24\n{}\n", text.lines().skip(2).map(|r| format!("{}-{}", r, r)).collect::<Vec<_>>().join("\n"));
+    let mut parallel = Board::parse_cg(&text).unwrap();
+    let limits = Limits { steps: Some(10), ..Limits::default() };
+    assert_eq!(parallel.solve_parallel(&limits, 4), Outcome::Incomplete(Stop::Steps));
+    assert_eq!(parallel.stats.pops, 10);
+    let limits = Limits { deadline: Some(Instant::now()), ..Limits::default() };
+    assert_eq!(parallel.solve_parallel(&limits, 4), Outcome::Incomplete(Stop::Deadline));
+    assert_eq!(parallel.stats.pops, 10);
+    assert_eq!(parallel.solve_parallel(&Limits::default(), 4), Outcome::Complete);
+    let mut single = Board::parse_cg(&text).unwrap();
+    single.solve(&Limits::default());
+    assert_eq!(parallel.to_rows(), single.to_rows());
+}
+
+#[test]
+fn batch_isolated() {
+    let input = "2 3\n1\n.1?\n.1?\n\nbad\n1 3\n1\n.1?\n1 2\n1\n1.\n";
+    let jobs = read_jobs(input.as_bytes(), "cg").unwrap();
+    assert_eq!(jobs.len(), 4);
+
+    let mut options = Options::new();
+    options.threads = 3;
+    let results = run_batch(&jobs, &options);
+    let ok: Vec<Option<&Json>> = results.iter().map(|r| r.get("ok")).collect();
+    assert_eq!(ok, vec![Some(&Json::Bool(true)), Some(&Json::Bool(false)), Some(&Json::Bool(true)), Some(&Json::Bool(false))]); // last one panics
+    assert_eq!(results[2].get("bombs"), Some(&Json::Arr(vec![Json::Arr(vec![Json::Int(2), Json::Int(0)])])));
+    assert_eq!(results[3].get("id"), Some(&Json::Int(4)));
+}
+
+#[test]
+fn check_answer() {
+    let board = Board::from_reader(&mut "3 4\n3\n?1..\n?211\n??1?\n".as_bytes());
+    assert_eq!(board.check_bombs(&[(0,1,1), (0,2,1), (3,2,1)]), vec![]);
+
+    assert_eq!(board.check_bombs(&[(0,1,1), (0,2,1), (0,2,1), (4,0,1)]), vec![
+        Violation::Twice(0, 2),
+        Violation::Outside(4, 0),
+        Violation::Digit { x: 2, y: 1, digit: 1, bombs: 0 },
+        Violation::Digit { x: 3, y: 1, digit: 1, bombs: 0 },
+        Violation::Digit { x: 2, y: 2, digit: 1, bombs: 0 },
+        Violation::Total { expected: "3".to_string(), bombs: 2 },
+    ]);
+    assert_eq!(board.check_bombs(&[(0,1,1), (0,2,1), (3,2,1), (1,0,1)])[0], Violation::Revealed(1, 0));
+    assert_eq!(board.check_bombs(&[(0,1,1), (0,2,2), (3,2,1)])[0], Violation::Range { x: 0, y: 2, mines: 2 });
+
+    assert_eq!(parse_bombs("0 2\n3 2 1\n\n").unwrap(), vec![(0,2,1), (3,2,1)]);
+    assert_eq!(parse_bombs("0 2 -1\n").unwrap(), vec![(0,2,-1)]);
+    assert!(parse_bombs("0 2 1 1\n").is_err());
+    assert!(parse_bombs("0 -2\n").is_err());
+}
+
+#[test]
+fn variant_clues() {
+    // ambiguous without the row total
+    let text = "2 3\n1 1\n?1.\n?1.\nrow 0 1\n";
+    let mut board = Board::from_reader(&mut text.as_bytes());
+    assert_eq!(board.clues, vec!["row 0 1"]);
+    board.process_stack();
+    assert_eq!(board.bombs_xy(), vec![(0,0)]);
+    assert_eq!(board.check_bombs(&[(0,1,1)]), vec![Violation::Clue { clue: "row 0 1".to_string(), bombs: 0 }]);
+
+    let mut out: Vec<u8> = Vec::new();
+    board.write_ascii(&mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "2 3\n1 1\n*1.\n-1.\nrow 0 1\n");
+
+    let mut board = Board::parse_cg("2 3\n1 1\n?1.\n?1.\nregion 0 1,0 0,1\n").unwrap();
+    board.process_stack();
+    assert_eq!(board.bombs_xy(), vec![(0,0)]);
+    let board = Board::from_json(&Json::parse(r#"{"width":3,"height":2,"bombs":1,"rows":["?1.","?1."],"clues":["col 0 1"]}"#).unwrap()).unwrap();
+    assert_eq!(board.hints.last().map(|h| h.squares.clone()), Some(vec![0, 3]));
+
+    assert!(Board::parse_cg("2 3\n1 1\n?1.\n?1.\nrow 2 1\n").is_err());
+    assert!(Board::parse_cg("2 3\n1 1\n?1.\n?1.\ncol 0 3\n").is_err());
+    assert!(Board::parse_cg("2 3\n1 2\n?1.\n?1.\ncol 0 1\n").is_err());
+}
+
+#[test]
+fn variant_mines() {
+    // up to 2 mines a square: 3 = 1 + 2 and 4 = 2 + 2
+    let text = "1 4\n5 3\n? ? ? ?\nmines 0 2\nregion 3 0,0 1,0 2,0\nregion 4 2,0 3,0\n";
+    let mut board = Board::parse_cg(text).unwrap();
+    board.process_stack();
+    assert_eq!(board.mines_xy(), vec![(2,0,2), (3,0,2)]);
+    assert_eq!(board.check_bombs(&[(1,0,1), (2,0,2), (3,0,2)]), vec![]);
+    assert_eq!(board.check_bombs(&[(2,0,1), (3,0,3)]), vec![
+        Violation::Range { x: 3, y: 0, mines: 3 },
+        Violation::Clue { clue: "region 3 0,0 1,0 2,0".to_string(), bombs: 1 },
+        Violation::Total { expected: "5".to_string(), bombs: 4 },
+    ]);
+    assert_eq!(board.to_rows(), vec!["? ? =2 =2"]);
+    assert!(board.to_layout().is_err());
+
+    // negative mines
+    let text = "1 5\n0 2\n? 0 ? -1 ?\nmines -1 1\nregion 1 0,0\n";
+    let mut board = Board::parse_cg(text).unwrap();
+    board.process_stack();
+    assert_eq!(board.mines_xy(), vec![(0,0,1), (2,0,-1)]);
+    assert_eq!(board.check_bombs(&board.mines_xy()), vec![]);
+    assert_eq!(board.to_rows(), vec!["* . =-1 -1 -"]);
+    let resumed = Board::from_snapshot(&board.to_snapshot()).unwrap();
+    assert_eq!((resumed.range, resumed.to_rows()), ((-1, 1), board.to_rows()));
+
+    assert!(Board::parse_cg("1 2\n1 1\n? 5\nmines 0 2\n").is_err());
+    assert!(Board::parse_cg("1 2\n1 1\n? 1\nmines 2 0\n").is_err());
+    assert!(Board::parse_cg("1 3\n? 2\n0 ? 64\nmines 0 64\nliar one 0,0 2,0\n").is_err());
+    let mut board = Board::parse_cg("1 3\n? 2\n0 ? 63\nmines 0 63\nliar one 0,0 2,0\n").unwrap();
+    board.process_stack();
+    assert_eq!(board.to_rows(), vec![". ? 63"]);
+}
+
+#[test]
+fn liar_clues() {
+    // 0 or 2 around the liar 1, the row leaves only 2
+    let text = "1 4\n2 2\n?1??\nliar\nrow 0 2\n";
+    let mut board = Board::parse_cg(text).unwrap();
+    assert_eq!(board.hints[0].alt, Some(2));
+    let snapshot = board.to_snapshot();
+    assert_eq!(Board::from_snapshot(&snapshot).unwrap().to_snapshot(), snapshot);
+    board.process_stack();
+    assert_eq!(board.bombs_xy(), vec![(0,0), (2,0)]);
+    assert_eq!(board.to_rows(), vec!["*1*-"]);
+    assert_eq!(board.stats.rules[Rule::Search as usize].get(), 1);
+    assert_eq!(board.check_bombs(&[(0,0,1), (2,0,1)]), vec![]);
+
+    // neither 1 nor 3 around the liar 2 between two bombs
+    assert!(Board::parse_cg("1 3\n2 1\n*2*\nliar\n").is_err());
+    assert!(Board::parse_cg("1 3\n2 1\n*2?\nliar\n").is_ok());
+
+    // known square rules out one count
+    let mut board = Board::parse_cg("1 3\n2 2\n?1?\nliar\nregion 1 0,0\n").unwrap();
+    board.process_stack();
+    assert_eq!(board.bombs_xy(), vec![(0,0), (2,0)]);
+    assert_eq!(board.stats.rules[Rule::Liar as usize].get(), 1);
+
+    // search too big for the node cap, or cut short by the deadline: nothing forced is claimed
+    let rows: Vec<String> = (0..12).map(|y| (0..12).map(|x| if x % 2 == 1 && y % 2 == 1 { '4' } else { '?' }).collect()).collect();
+    let text = format!("12 12\n30 1\n{}\nliar\n", rows.join("\n"));
+    let mut board = Board::parse_cg(&text).unwrap();
+    assert_eq!(board.solve(&Limits::default()), Outcome::Incomplete(Stop::Nodes));
+    let mut board = Board::parse_cg(&text).unwrap();
+    let limits = Limits { deadline: Some(Instant::now() + Duration::from_millis(20)), ..Limits::default() };
+    assert_eq!(board.solve(&limits), Outcome::Incomplete(Stop::Deadline));
+
+    let board = Board::parse_cg("1 3\n1 1\n?.?\nliar row 0 1\n").unwrap();
+    assert_eq!((board.hints[0].bombs, board.hints[0].alt), (0, Some(2)));
+    assert!(Board::parse_cg("1 2\n1 1\n?3\nliar\n").is_err());
+}
+
+#[test]
+fn liar_one() {
+    // 2 can't be right with one square around, so both 1s are
+    let mut board = Board::parse_cg("1 5\n1 1\n2?1?1\nliar one 0,0 2,0 4,0\n").unwrap();
+    assert_eq!(board.hints.iter().filter(|h| h.lie == Some(0)).count(), 3);
+    board.process_stack();
+    assert_eq!(board.to_rows(), vec!["2-1*1"]);
+    assert_eq!(board.stats.rules[Rule::Search as usize].get(), 1);
+    assert_eq!(board.hints.iter().filter(|h| h.valid && h.lie.is_some()).count(), 1);
+    assert_eq!(board.check_bombs(&[(3,0,1)]), vec![]);
+    assert_eq!(board.check_bombs(&[(1,0,1)]), vec![Violation::Lies { clue: "liar one 0,0 2,0 4,0".to_string(), wrong: 2 }]);
+
+    // one wrong digit in each row, the rows share squares
+    let text = "2 5\n2 2\n11?12\n??2?3\nliar one 0,0 1,0 3,0 4,0\nliar one 2,1 4,1\n";
+    let mut board = Board::parse_cg(text).unwrap();
+    let snapshot = board.to_snapshot();
+    assert_eq!(Board::from_snapshot(&snapshot).unwrap().to_snapshot(), snapshot);
+    let mut parallel = Board::parse_cg(text).unwrap();
+    board.process_stack();
+    assert_eq!(board.to_rows(), vec!["11-12", "-*2*3"]);
+    assert_eq!(parallel.solve_parallel(&Limits::default(), 2), Outcome::Complete);
+    assert_eq!(parallel.to_rows(), board.to_rows());
+    assert_eq!(board.check_bombs(&board.mines_xy()), vec![]);
+
+    assert!(Board::parse_cg("1 3\n1 1\n1?1\nliar one 1,0\n").is_err());
+    assert!(Board::parse_cg("1 3\n1 1\n1?1\nliar one 0,0 0,0\n").is_err());
+    assert!(Board::parse_cg("1 3\n1 2\n1?1\nliar\nliar one 0,0\n").is_err());
+}
+
+#[test]
+fn hint_either() {
+    assert_eq!(Hint::either(3, 1, vec![2, 1]).map(|h| (h.bombs, h.alt, h.squares)), Some((1, Some(3), vec![1, 2])));
+    assert_eq!(Hint::either(2, 2, vec![1, 2]).map(|h| (h.bombs, h.alt)), Some((2, None)));
+    assert!(! Hint::either(0, 2, vec![]).unwrap().valid);
+    assert!(Hint::either(1, 3, vec![]).is_none());
+}
+
+#[test]
+fn board_3d() {
+    let text = "2 2 2\n1\n?1\n11\n11\n11\n";
+    let mut board = Board::from_reader(&mut text.as_bytes());
+    assert_eq!((board.d, board.squares.len(), board.hints.len()), (2, 8, 7));
+    assert_eq!(board.hints[0].squares.len(), 7);
+    board.process_stack();
+    assert_eq!(board.bombs_xyz(), vec![(0,0,0)]);
+    assert_eq!(board.to_rows(), vec!["*1", "11", "11", "11"]);
+
+    let mut out: Vec<u8> = Vec::new();
+    board.write_ascii(&mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "2 2 2\n1\n*1\n11\n11\n11\n");
+    assert_eq!(Board::from_json(&board.to_json()).unwrap().to_json(), board.to_json());
+    assert_eq!(Board::from_snapshot(&board.to_snapshot()).unwrap().to_snapshot(), board.to_snapshot());
+    assert!(board.to_layout().is_err());
+
+    let middle = Board::new(3, 3, 3, 0);
+    assert_eq!(middle.bomb2hint(1, 1, 1, 0).squares.len(), 26);
+    assert_eq!(middle.bomb2hint(0, 0, 2, 0).squares.len(), 7);
+
+    let jobs = read_jobs(format!("{}\n{}", text, text).as_bytes(), "cg").unwrap();
+    assert_eq!(jobs.len(), 2);
+    assert_eq!(run_job(&jobs[1], "cg", &Options::new()).get("bombs"),
+               Some(&Json::Arr(vec![Json::Arr(vec![Json::Int(0), Json::Int(0), Json::Int(0)])])));
+    assert!(Board::parse_cg("2 2 2\n1 1\n?1\n11\n11\n11\nrow 0 1\n").is_err());
+
+    // counts above 9 need spaced rows to be read back
+    let layer = ["? ? ?", "? ? ?", "? ? ?"];
+    let text = format!("3 3 3\n26\n{}\n? ? ?\n? 26 ?\n? ? ?\n{}\n", layer.join("\n"), layer.join("\n"));
+    let mut board = Board::from_reader(&mut text.as_bytes());
+    board.process_stack();
+    assert_eq!(board.to_rows()[4], "* 26 *");
+    let mut out: Vec<u8> = Vec::new();
+    board.write_ascii(&mut out).unwrap();
+    let read = Board::from_reader(&mut out.as_slice());
+    assert_eq!((read.to_rows(), read.digits[13]), (board.to_rows(), Some(26)));
+    assert_eq!(Board::from_json(&board.to_json()).unwrap().to_json(), board.to_json());
+}
+
+#[test]
+fn parallel_regions() {
+    // two copies of a puzzle, side by side: nothing links them
+    let rows = ["?1....1??", "?2....12?", "?1.....11", "11.......", "12221..11", "????2112?", "????21???", "????32???", "????2????"];
+    let text = format!("9 19\n24\n{}\n", rows.iter().map(|r| format!("{}-{}", r, r)).collect::<Vec<_>>().join("\n"));
+
+    let mut single = Board::parse_cg(&text).unwrap();
+    single.solve(&Limits::default());
+    let mut parallel = Board::parse_cg(&text).unwrap();
+    assert!(parallel.regions().len() >= 2);
+    assert_eq!(parallel.solve_parallel(&Limits::default(), 3), Outcome::Complete);
+    assert_eq!(parallel.to_rows(), single.to_rows());
+    assert_eq!(parallel.bombs_xy(), single.bombs_xy());
+    assert_eq!(parallel.stats.hints_alive, parallel.hints.iter().filter(|h| h.valid).count());
+    assert_eq!(parallel.stats.hints_alive as u64, parallel.stats.hints_added - parallel.stats.hints_removed);
+    assert_eq!((parallel.stats.hints_added, parallel.stats.hints_removed), (single.stats.hints_added, single.stats.hints_removed));
+    assert!(parallel.stats.hints_peak <= single.stats.hints_peak);
+
+    for input in [include_str!("i01"), include_str!("i03"), include_str!("i05")].iter() {
+        let mut single = Board::parse_cg(input).unwrap();
+        single.solve(&Limits::default());
+        let mut parallel = Board::parse_cg(input).unwrap();
+        parallel.solve_parallel(&Limits::default(), 4);
+        assert_eq!(parallel.to_rows(), single.to_rows());
+    }
+}
+
+#[test]
+fn random_soundness() {
+    let seed = env::var("CG_TEST_SEED").ok().and_then(|s| s.parse::<u64>().ok()).unwrap_or(2018);
+    let mut rng = Rng::new(seed);
+    for i in 0..500 {
+        let case = Case::random(&mut rng);
+        if case.check().is_some() {
+            let small = case.shrink(&|c: &Case| c.check());
+            panic!("seed {}, case {}: {}\n{}", seed, i, small.check().unwrap(), small);
+        }
+    }
+}
+
+#[test]
+fn random_shrink() {
+    let mut rng = Rng::new(7);
+    let case = (0..).map(|_| Case::random(&mut rng)).find(|c| c.layout.n_mines() >= 3 && !c.rows.is_empty()).unwrap();
+    let small = case.shrink(&|c: &Case| if c.layout.n_mines() >= 2 { Some("2 mines".to_string()) } else { None });
+    assert_eq!(small.layout.n_mines(), 2);
+    assert!(small.revealed.iter().all(|&r| ! r) && small.rows.is_empty());
+    assert!(small.smaller().iter().all(|c| c.layout.n_mines() < 2));
+}
+
+#[test]
+fn proof_digits() {
+    // (2,1) is the only unknown around the bottom 1s, (2,0) needs one of them too
+    let board = Board::parse_cg("3 3\n1\n.1?\n.1?\n.11\n").unwrap();
+    let (v, digits) = board.proof(board.xy2s(2, 1)).unwrap();
+    assert_eq!(v, 1);
+    assert_eq!(digits.len(), 1);
+    assert!(digits[0] == board.xy2s(1, 2) || digits[0] == board.xy2s(2, 2));
+    let (v, digits) = board.proof(board.xy2s(2, 0)).unwrap();
+    assert_eq!((v, digits.len()), (0, 2));
+    assert_eq!(board.proof(board.xy2s(0, 0)), Some((0, vec![])));
+
+    // forced by the total only
+    let board = Board::parse_cg("2 2\n1\n1?\n??\n").unwrap();
+    assert_eq!(board.proof(board.xy2s(1, 1)), None);
+
+    // liar: 0 or 2 in the two squares, the 3 (2 or 4) makes it 2
+    let board = Board::parse_cg("2 3\n2 1\n?1?\n3??\nliar\n").unwrap();
+    let (v, digits) = board.proof(board.xy2s(0, 0)).unwrap();
+    assert_eq!(v, 1);
+    assert!(digits.len() <= 2);
+}
+
+#[test]
+fn unknown_total() {
+    // only the total can tell
+    let mut board = Board::parse_cg("1 3\n?\n?-?\n").unwrap();
+    board.process_stack();
+    assert_eq!((board.total(), board.to_rows()), ("?".to_string(), vec![format!("?-?")]));
+    assert_eq!(board.check_bombs(&[(0,0,1)]), vec![]);
+    let json = board.to_json();
+    assert_eq!(json.get("bombs"), Some(&Json::Str("?".to_string())));
+    assert_eq!(Board::from_json(&json).unwrap().bombs_max, u32::MAX);
+
+    let mut board = Board::parse_cg("1 3\n2-3\n?-?\n").unwrap();
+    board.process_stack();
+    assert_eq!(board.bombs_xy(), vec![(0,0), (2,0)]);
+    assert_eq!(board.check_bombs(&[(0,0,1)]), vec![Violation::Total { expected: "2-3".to_string(), bombs: 1 }]);
+    let mut text: Vec<u8> = Vec::new();
+    board.write_ascii(&mut text).unwrap();
+    assert_eq!(String::from_utf8(text).unwrap(), "1 3\n2-3\n*-*\n");
+
+    // at most 1, and it is known
+    let mut board = Board::parse_cg("1 3\n0-1\n*-?\n").unwrap();
+    board.process_stack();
+    assert_eq!(board.to_rows(), vec!["*--"]);
+
+    assert_eq!(parse_total("4"), Some((4, 4)));
+    assert_eq!(parse_total("3-2"), None);
+    assert!(Board::parse_cg("1 3\nx\n?-?\n").is_err());
+}
+
+#[test]
+fn plan_moves() {
+    // the game: clicks never hit a bomb, chords only on digits with their bombs flagged
+    fn play(layout: &Layout, revealed: &[bool], plan: &[Action]) -> Vec<bool> {
+        let mut game = Game::new(layout.clone());
+        game.revealed = revealed.to_vec();
+        for &action in plan {
+            game.apply(action).unwrap();
+        }
+        game.revealed
+    }
+
+    // 22 empty squares: two clicks flood most, one flag serves two chords
+    let layout = Layout::from_ascii(".....\n.*...\n....*\n.....\n.*...\n").unwrap();
+    let mut revealed = vec![false; 25];
+    revealed[layout.xy2s(2, 2)] = true;
+    let board = Board::parse_cg("5 5\n3\n-----\n-*---\n--1-*\n-----\n-*---\n").unwrap();
+    let plan = board.plan();
+    assert_eq!(plan, vec![Action::Click(3,0), Action::Click(3,4), Action::Flag(1,1), Action::Chord(2,1), Action::Chord(1,2),
+                          Action::Click(0,0), Action::Click(0,4)]);
+    assert!(play(&layout, &revealed, &plan).iter().zip(layout.mines.iter()).all(|(&open, &mine)| open != mine));
+
+    let mut rng = Rng::new(45);
+    for _ in 0..200 {
+        let case = Case::random(&mut rng);
+        let mut board = case.board();
+        board.process_stack();
+        let plan = board.plan();
+        let open = play(&case.layout, &case.revealed, &plan);
+        let targets = (0..open.len()).filter(|&s| board.squares[s].state == SquareState::Empty && ! case.revealed[s]).count();
+        assert!((0..open.len()).all(|s| open[s] || board.squares[s].state != SquareState::Empty));
+        assert!(plan.len() <= targets);
+    }
+}
+
+#[test]
+fn plan_minimum() {
+    // fewest actions by breadth-first search over (revealed, flagged), on small boards
+    fn minimum(layout: &Layout, revealed: &[bool], board: &Board) -> usize {
+        let empty: Vec<usize> = (0..revealed.len()).filter(|&s| board.squares[s].state == SquareState::Empty).collect();
+        let mut game = Game::new(layout.clone());
+        game.revealed = revealed.to_vec();
+        let mut seen: std::collections::HashSet<(Vec<bool>, Vec<bool>)> = std::collections::HashSet::new();
+        let mut level: Vec<Game> = vec![game];
+        for depth in 0.. {
+            if level.iter().any(|g| empty.iter().all(|&s| g.revealed[s])) {
+                return depth;
+            }
+            let mut next: Vec<Game> = Vec::new();
+            for game in level.iter() {
+                for s in 0..revealed.len() {
+                    let (x, y, _) = board.s2xyz(s);
+                    let state = board.squares[s].state;
+                    let mut actions = vec![Action::Chord(x, y)];
+                    if state == SquareState::Empty && ! game.revealed[s] { actions.push(Action::Click(x, y)); }
+                    if state == SquareState::Bomb && ! game.flagged[s] { actions.push(Action::Flag(x, y)); }
+                    for action in actions {
+                        let mut after = Game { layout: game.layout.clone(), revealed: game.revealed.clone(), flagged: game.flagged.clone() };
+                        if after.apply(action).is_ok() && seen.insert((after.revealed.clone(), after.flagged.clone())) {
+                            next.push(after);
+                        }
+                    }
+                }
+            }
+            level = next;
+        }
+        unreachable!()
+    }
+
+    // solved boards only: with unknown squares left, a lucky click may open more than the planner knows
+    let (mut cases, mut extra) = (0, 0);
+    let mut rng = Rng::new(45);
+    for seed in 0..1000 {
+        let (w, h) = (3 + seed as u32 % 4, 3 + seed as u32 / 4 % 3);
+        let layout = Layout::random(w, h, 2 + seed as u32 % 5, seed);
+        let mut revealed = layout.opening();
+        for s in (0..revealed.len()).filter(|&s| ! layout.mines[s]) {
+            revealed[s] |= rng.chance(30);
+        }
+        let mut board = layout.to_board(&revealed);
+        board.process_stack();
+        if board.squares.iter().any(|sq| sq.state == SquareState::Unknown) { continue; }
+        let plan = board.plan();
+        let best = minimum(&layout, &revealed, &board);
+        assert!(plan.len() >= best, "seed {}: {:?} under the minimum {}", seed, plan, best);
+        cases += 1;
+        extra += plan.len() - best;
+    }
+    // greedy: 3 plans take one action more than needed, no more of them should
+    assert_eq!(cases, 270);
+    assert!(extra <= 3, "{} actions over the minimum", extra);
+}
+
+#[test]
+fn replay_log() {
+    let log = Game::play(Layout::random(16, 16, 40, 5), Some(5));
+    let log = Json::parse(&log.to_string()).unwrap();
+    assert_eq!(log.get("layout").map(|l| Layout::from_json(l).unwrap().n_mines()), Some(40));
+    assert!(log.get_arr("steps").unwrap().len() > 1);
+    assert_eq!(Game::replay(&log).unwrap(), Vec::<String>::new());
+
+    // as if an older solver had missed a bomb
+    let mut changed = log.clone();
+    if let Json::Obj(ref mut pairs) = changed {
+        if let Json::Arr(ref mut steps) = pairs[3].1 {
+            if let Json::Obj(ref mut step) = steps[0] {
+                step[2].1 = Json::Arr(vec![]);
+            }
+        }
+    }
+    let diffs = Game::replay(&changed).unwrap();
+    assert_eq!(diffs.len(), 1);
+    assert!(diffs[0].starts_with("step 0: bombs was []"));
+
+    assert_eq!(Action::parse("chord 2 1"), Ok(Action::Chord(2, 1)));
+    assert!(Action::parse("click 2").is_err());
+    let mut game = Game::new(Layout::from_ascii("*.\n..\n").unwrap());
+    assert!(game.apply(Action::Click(0, 0)).is_err());
+    assert!(game.apply(Action::Chord(1, 1)).is_err());
+}
@@ -1,6 +1,20 @@
 use std::io;
+use std::env;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+// log!(LogLevel::Step, "event", key = value, ...), see Logging
+macro_rules! log {
+    ($level:expr, $event:expr $(, $key:ident = $value:expr)*) => (
+        if log_enabled($level) {
+            let fields: Vec<(&str, String)> = vec![$((stringify!($key), format!("{}", $value))),*];
+            eprintln!("{}", log_line($event, &fields, LOG_EVENTS.load(Ordering::Relaxed)));
+        }
+    )
+}
 
 fn main() {
+    log_init();
+
     let mut input_line = String::new();
     io::stdin().read_line(&mut input_line).unwrap();
     let r_1 = input_line.trim().parse::<u32>().unwrap();
@@ -10,11 +24,13 @@ fn main() {
     let r_2 = input_line.trim().parse::<u32>().unwrap();
 
     // some tests
-    eprintln!("sumdigits(1)={}", sumdigits(1));
-    eprintln!("sumdigits(435)={}", sumdigits(435));
-    eprintln!("meeting_point(1,7)={}", meeting_point(1, 7));
+    log!(LogLevel::Step, "test", sumdigits_1 = sumdigits(1));
+    log!(LogLevel::Step, "test", sumdigits_435 = sumdigits(435));
+    log!(LogLevel::Step, "test", meeting_point_1_7 = meeting_point(1, 7));
     
-    println!("{}", meeting_point(r_1, r_2));
+    let meeting = meeting_point(r_1, r_2);
+    log!(LogLevel::Summary, "meeting", r1 = r_1, r2 = r_2, point = meeting);
+    println!("{}", meeting);
 }
 
 fn sumdigits(num: u32) -> u32 {
@@ -33,6 +49,7 @@ fn meeting_point(r1: u32, r2: u32) -> u32 {
     let mut n2 = r2;
     
     loop {
+        log!(LogLevel::Step, "step", n1 = n1, n2 = n2);
         if n1 == n2 { return n1 }
         if n1 < n2 {
             n1 += sumdigits(n1);
@@ -42,3 +59,67 @@ fn meeting_point(r1: u32, r2: u32) -> u32 {
     }
 }
 
+/* ------------------------------------------------------------- Logging */
+// Goes to stderr. Level from --log or CG_LOG (default summary):
+//   quiet    nothing
+//   summary  result
+//   step     every step of both rivers, and a few sanity checks of the helpers
+// --log-events or CG_LOG_FORMAT=events writes one "event=NAME key=value ..." line
+// per event instead, values quoted and escaped when needed, for tooling.
+#[derive(PartialEq,PartialOrd,Clone,Copy,Debug)]
+enum LogLevel {
+    Quiet,
+    Summary,
+    Step,
+}
+
+static LOG_LEVEL: AtomicUsize = AtomicUsize::new(LogLevel::Summary as usize);
+static LOG_EVENTS: AtomicBool = AtomicBool::new(false);
+
+impl LogLevel {
+    fn parse(name: &str) -> Option<LogLevel> {
+        match name {
+            "quiet" => Some(LogLevel::Quiet),
+            "summary" => Some(LogLevel::Summary),
+            "step" => Some(LogLevel::Step),
+            _ => None,
+        }
+    }
+}
+
+// --log LEVEL and --log-events arguments win over environment
+fn log_init() {
+    let mut level = env::var("CG_LOG").ok();
+    let mut events = env::var("CG_LOG_FORMAT").map(|f| f == "events").unwrap_or(false);
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--log" => level = Some(args.next().expect("--log needs a level")),
+            "--log-events" => events = true,
+            _ => panic!("unknown option {}", arg),
+        }
+    }
+
+    if let Some(name) = level {
+        let level = LogLevel::parse(&name).expect("log level: quiet, summary or step");
+        LOG_LEVEL.store(level as usize, Ordering::Relaxed);
+    }
+    LOG_EVENTS.store(events, Ordering::Relaxed);
+}
+
+fn log_enabled(level: LogLevel) -> bool {
+    level != LogLevel::Quiet && level as usize <= LOG_LEVEL.load(Ordering::Relaxed)
+}
+
+fn log_line(event: &str, fields: &[(&str, String)], events: bool) -> String {
+    let mut line = if events { format!("event={}", event) } else { event.to_string() };
+    for &(key, ref value) in fields {
+        let plain = ! value.is_empty() && value.chars().all(|c| c.is_alphanumeric() || "_-.,@()".contains(c));
+        if ! events || plain {
+            line.push_str(&format!(" {}={}", key, value));
+        } else {
+            line.push_str(&format!(" {}={:?}", key, value));  // quoted, \n and " escaped
+        }
+    }
+    line
+}
@@ -1,23 +1,62 @@
 use std::io;
+use std::env;
 use std::fmt;
 use std::vec::Vec;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use self::PieceKind::*;
 
+// log!(LogLevel::Group, "event", key = value, ...), see Logging
+macro_rules! log {
+    ($level:expr, $event:expr $(, $key:ident = $value:expr)*) => (
+        if log_enabled($level) {
+            let fields: Vec<(&str, String)> = vec![$((stringify!($key), format!("{}", $value))),*];
+            eprintln!("{}", log_line($event, &fields, LOG_EVENTS.load(Ordering::Relaxed)));
+        }
+    )
+}
+
 fn main() {
+    let options = Options::from_args();
+    log_init(options.log.as_deref(), options.log_events);
+
     let mut input_line = String::new();
     io::stdin().read_line(&mut input_line).unwrap();
-    let n = input_line.trim().parse::<u32>().unwrap();
+    let n = input_line.trim();
+    assert!(! n.is_empty() && n.chars().all(|c| c.is_ascii_digit()), "n: decimal number expected");
+    let n = if n.trim_start_matches('0').is_empty() { "0" } else { n.trim_start_matches('0') };
 
-    let mut board = Board::from_stdin();
-    eprintln!("{}", board);
+    let mut board = Board::from_stdin().unwrap();
+    log!(LogLevel::Board, "board", board = board);
 
     board.group();
-    eprintln!("{}", board);
+    log!(LogLevel::Board, "grouped", board = board);
     
     let edg_vec = board.analysis();
-    eprintln!("Groups: {:?}", edg_vec);
+    log!(LogLevel::Summary, "groups", n = edg_vec.len(), edges = format!("{:?}", edg_vec));
 
-    println!("{}", answer(edg_vec, n));
+    if options.draw || options.simulate {
+        let too_large = || -> ! {
+            eprintln!("n={}: sheet too large to unfold for real, above {} cells", n, SIMULATE_MAX_CELLS);
+            std::process::exit(1);
+        };
+        let n = n.parse::<u32>().unwrap_or_else(|_| too_large());
+        if options.draw {
+            print!("{}", board.draw(n).unwrap_or_else(|| too_large()));
+        } else {
+            println!("{}", board.simulate(n).unwrap_or_else(|| too_large()));
+        }
+        return;
+    }
+    match options.modulus {
+        Some(m) => println!("{}", answer(&edg_vec, n, &Modular { value: 0, m })),
+        None => {
+            if n.parse::<usize>().map_or(true, |n| n > EXACT_MAX_N) {
+                eprintln!("n above {}: too large for an exact answer, use --mod M", EXACT_MAX_N);
+                std::process::exit(1);
+            }
+            println!("{}", answer(&edg_vec, n, &Big(Vec::new())));
+        },
+    }
 }
 
 struct Spot {
@@ -29,7 +68,7 @@ struct Spot {
 enum PieceKind {
     Empty,      // empty space
     Piece,      // paper, not groupped yet
-    Group(u16), // paper, groupped
+    Group(u32), // paper, groupped
 }
 
 #[ derive(Default,Clone) ]
@@ -51,39 +90,59 @@ impl fmt::Debug for GroupEdges
 }
 
 struct Board {
-    pieces: [[PieceKind; 100]; 100], // rows of columns, [row][column], [y][x]
+    pieces: Vec<Vec<PieceKind>>, // h rows of w columns, [row][column], [y][x]
     w: usize,
     h: usize,
-    groups: u16,
+    groups: u32,
 }
 
 impl Board {
-    fn from_stdin() -> Board {
+    // "w h", then h rows
+    fn from_stdin() -> Result<Board, String> {
         let mut input_line = String::new();
         io::stdin().read_line(&mut input_line).unwrap();
-        let inputs = input_line.split(" ").collect::<Vec<_>>();
-
-        let w = inputs[0].trim().parse::<usize>().unwrap();
-        let h = inputs[1].trim().parse::<usize>().unwrap();
-        let mut pieces: [[PieceKind; 100]; 100] = [[Empty; 100]; 100];
+        let inputs = input_line.split_whitespace().collect::<Vec<_>>();
+        if inputs.len() != 2 {
+            return Err(format!("bad header '{}'", input_line.trim()));
+        }
+        let w = inputs[0].parse::<usize>().map_err(|_| format!("bad width '{}'", inputs[0]))?;
+        let h = inputs[1].parse::<usize>().map_err(|_| format!("bad height '{}'", inputs[1]))?;
 
-        for r in 0..h {
+        let mut rows: Vec<String> = Vec::with_capacity(h);
+        for _ in 0..h {
             let mut input_line = String::new();
             io::stdin().read_line(&mut input_line).unwrap();
-            let row = input_line.trim_right().to_string();
+            rows.push(input_line.trim_end().to_string());
+        }
+        Board::from_rows(w, h, &rows)
+    }
 
-            for (i,c) in row.chars().enumerate() {
-                if c == '#' {
-                    pieces[r][i] = Piece;
-                }
+    // rows of '#' (paper) and '.', each exactly w long
+    fn from_rows(w: usize, h: usize, rows: &[String]) -> Result<Board, String> {
+        if w == 0 || h == 0 {
+            return Err(format!("empty sheet {}x{}", w, h));
+        }
+        if rows.len() != h {
+            return Err(format!("{} rows expected, got {}", h, rows.len()));
+        }
+        let mut pieces: Vec<Vec<PieceKind>> = Vec::with_capacity(h);
+        for (y,row) in rows.iter().enumerate() {
+            if row.chars().count() != w {
+                return Err(format!("row {}: {} long, {} expected", y, row.chars().count(), w));
             }
+            let line = row.chars().map(|c| match c {
+                '#' => Ok(Piece),
+                '.' => Ok(Empty),
+                _ => Err(format!("row {}: bad character '{}'", y, c)),
+            }).collect::<Result<Vec<_>, _>>()?;
+            pieces.push(line);
         }
 
-        Board { pieces: pieces, w:w, h:h, groups: 0}
+        Ok(Board { pieces, w, h, groups: 0})
     }
 
     fn group(&mut self) {
-        let mut gr : u16 = 0;
+        let mut gr : u32 = 0;
         for y in 0..self.h {
             for x in 0..self.w {
                 if self.pieces[y][x] == Piece {
@@ -95,7 +154,7 @@ impl Board {
         self.groups = gr;
     }
     
-    fn flood_group(&mut self, x: usize, y: usize, gr: u16) {
+    fn flood_group(&mut self, x: usize, y: usize, gr: u32) {
         let mut stack : Vec<Spot> = Vec::new();
         stack.push(Spot {x,y});
         
@@ -140,8 +199,8 @@ impl Board {
     fn right(&self, spot: &Spot) -> Option<Spot> { if spot.x+1 < self.w { Some(Spot{x:spot.x+1, y:spot.y,})} else { None } }
     fn down (&self, spot: &Spot) -> Option<Spot> { if spot.y+1 < self.h { Some(Spot{x:spot.x, y:spot.y+1,})} else { None } }
 
-    fn set_group(&mut self, spot: &Spot, gr: u16) { self.pieces[spot.y][spot.x] = Group(gr); }
-    fn needs_group_at(&self, spot: &Spot, gr: u16) -> bool {
+    fn set_group(&mut self, spot: &Spot, gr: u32) { self.pieces[spot.y][spot.x] = Group(gr); }
+    fn needs_group_at(&self, spot: &Spot, gr: u32) -> bool {
         match self.pieces[spot.y][spot.x] {
             Empty => false,
             Piece => true,
@@ -160,42 +219,499 @@ impl fmt::Display for Board {
                     Group(gr) => write!(f, "{}", gr%10)?,
                 };
             }
-            writeln!(f, "")?;
+            writeln!(f)?;
         }
         Ok(())
     }
 }
 
-// can't be lazy evaluated before for loop -> see test nr 4 why
-fn U(n: u32) -> u32 { if n == 0 { 1 } else { 2_u32.pow(n-1) } }
-fn V(n: u32) -> u32 { 2_u32.pow(n) }
-fn W(n: u32) -> u32 { if n == 0 { 1 } else { 2_u32.pow(n-1) + 1 }}
+// n is decimal digits, unit tells how to count (Big or Modular): see Counting
+#[allow(non_snake_case)]
+fn U<T: Count>(n: &str, unit: &T) -> T { if n == "0" { unit.one() } else { unit.pow2(&decimal_pred(n)) } }
+#[allow(non_snake_case)]
+fn V<T: Count>(n: &str, unit: &T) -> T { unit.pow2(n) }
+#[allow(non_snake_case)]
+fn W<T: Count>(n: &str, unit: &T) -> T { if n == "0" { unit.one() } else { unit.pow2(&decimal_pred(n)).add(&unit.one()) }}
+
+// copies of a group along one direction, by the sheet edges it touches
+// (left and right, or top and bottom)
+#[derive(PartialEq, Copy, Clone, Debug)]
+enum Factor {
+    One,  // both
+    U,    // first only
+    V,    // none
+    W,    // second only
+}
+
+fn factor(first: bool, second: bool) -> Factor {
+    match (first, second) {
+        (true, true) => Factor::One,
+        (true, false) => Factor::U,
+        (false, false) => Factor::V,
+        (false, true) => Factor::W,
+    }
+}
 
 // see patterns.txt for explanation
-fn answer(edg_vec: Vec<GroupEdges>, n: u32) -> u32 {
+fn answer<T: Count>(edg_vec: &[GroupEdges], n: &str, unit: &T) -> T {
+    // can't be evaluated before the loop: huge when not needed -> see test nr 4
+    let mut values: Vec<Option<T>> = vec![None; 4];
+    let mut value = |f: Factor| -> T {
+        let i = f as usize;
+        if values[i].is_none() {
+            values[i] = Some(match f {
+                Factor::One => unit.one(),
+                Factor::U => U(n, unit),
+                Factor::V => V(n, unit),
+                Factor::W => W(n, unit),
+            });
+        }
+        values[i].clone().unwrap()
+    };
 
-    let mut sum = 0;
+    let mut sum = unit.zero();
     for (gr,edg) in edg_vec.iter().enumerate() {
-        let h_factor = match (edg.left, edg.right) {
-            (true, true) => 1,
-            (true, false) => U(n),
-            (false, false) => V(n),
-            (false, true) => W(n),
-        };
+        let h_factor = value(factor(edg.left, edg.right));
+        let v_factor = value(factor(edg.top, edg.bottom));
+        let pieces = h_factor.mul(&v_factor);
+
+        log!(LogLevel::Group, "group", gr = gr, h = h_factor, v = v_factor, pieces = pieces);
+        sum = sum.add(&pieces);
+    }
+
+    sum
+}
+
+/* ------------------------------------------------------------- Simulation */
+// Brute force to check answer() against: unfold the sheet for real, n times,
+// and count the groups of the unfolded sheet with flood_group(). An unfolding
+// mirrors the sheet to the left and to the top, as in patterns.txt.
+// Each one makes 4 times the cells, so small n only.
+const SIMULATE_MAX_CELLS: usize = 1 << 24;
+
+impl Board {
+    // mirrored copies on the left and on top, not grouped
+    fn unfold(&self) -> Board {
+        let mirror = |i: usize, size: usize| if i < size { size-1-i } else { i-size };
+        let pieces = (0..self.h*2).map(|y| (0..self.w*2).map(|x| {
+            match self.pieces[mirror(y, self.h)][mirror(x, self.w)] {
+                Empty => Empty,
+                _ => Piece,
+            }
+        }).collect()).collect();
+        Board { pieces, w: self.w*2, h: self.h*2, groups: 0 }
+    }
+
+    // pieces after n unfoldings, None above SIMULATE_MAX_CELLS
+    fn simulate(&self, n: u32) -> Option<u32> {
+        self.unfolded(n).map(|board| board.groups)
+    }
 
-        let v_factor = match (edg.top, edg.bottom) {
-            (true, true) => 1,
-            (true, false) => U(n),
-            (false, false) => V(n),
-            (false, true) => W(n),
+    // the sheet unfolded n times and grouped, None above SIMULATE_MAX_CELLS
+    fn unfolded(&self, n: u32) -> Option<Board> {
+        let cells = n.checked_mul(2).and_then(|shift| 1usize.checked_shl(shift)).and_then(|k| k.checked_mul(self.w*self.h));
+        if cells.map(|c| c > SIMULATE_MAX_CELLS).unwrap_or(true) {
+            return None;
+        }
+        let mut board = (0..n).fold(self.unfold_none(), |board, _| board.unfold());
+        board.group();
+        log!(LogLevel::Board, "unfolded", board = board);
+        Some(board)
+    }
+
+    // ungrouped copy
+    fn unfold_none(&self) -> Board {
+        let pieces = self.pieces.iter().map(|row| row.iter().map(|&p| if p == Empty { Empty } else { Piece }).collect()).collect();
+        Board { pieces, w: self.w, h: self.h, groups: 0 }
+    }
+}
+
+/* ------------------------------------------------------------- Drawing */
+// The unfolded sheet as in patterns.txt, for checking answer() by eye. Left,
+// every cell labelled with the group of the grouped sheet it is a copy of
+// (a..z, A..Z; above 52 groups labels of as many letters as needed, aa ab ..,
+// spaced); right, the pieces after unfolding (digits as in Display). Copies of
+// different groups sharing a piece have merged. A number ends the first row of
+// the sheet as it was after that many unfoldings:
+//   n=1
+//   b..b 0..1 1
+//   .aa. .22.
+//   .aa. .22. 0
+//   b..b 3..4
+const LETTERS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+// gr in base 52, width letters
+fn label(gr: u32, width: usize) -> String {
+    let mut rest = gr as usize;
+    let mut letters = vec!['a'; width];
+    for letter in letters.iter_mut().rev() {
+        *letter = LETTERS[rest % LETTERS.len()] as char;
+        rest /= LETTERS.len();
+    }
+    letters.into_iter().collect()
+}
+
+impl Board {
+    // None above SIMULATE_MAX_CELLS
+    fn draw(&self, n: u32) -> Option<String> {
+        let unfolded = self.unfolded(n)?;
+        // back through every unfolding to the cell of the original sheet
+        let origin = |mut i: usize, size: usize| {
+            for k in (0..n).rev() {
+                let half = size << k;
+                i = if i < half { half-1-i } else { i-half };
+            }
+            i
         };
+        let width = (1..).find(|&k| (LETTERS.len() as u64).pow(k) >= self.groups as u64).unwrap() as usize;
+        let pieces = unfolded.to_string();
+        let mut text = format!("n={}\n", n);
+        for (y, row) in pieces.lines().enumerate() {
+            let labels: Vec<String> = (0..unfolded.w).map(|x| match self.pieces[origin(y, self.h)][origin(x, self.w)] {
+                Group(gr) => label(gr, width),
+                _ => ".".repeat(width),
+            }).collect();
+            let labels = labels.join(if width > 1 { " " } else { "" });
+            text.push_str(&format!("{} {}", labels, row));
+            if let Some(k) = (0..n+1).find(|&k| y == unfolded.h - (self.h << k)) {
+                text.push_str(&format!(" {}", k));
+            }
+            text.push('\n');
+        }
+        Some(text)
+    }
+}
 
-        eprintln!("gr={} h={} x v={} = {}", gr, h_factor, v_factor, h_factor * v_factor);
-        sum += h_factor * v_factor;
+/* ------------------------------------------------------------- Counting */
+// answer() in exact big integers, or modulo M (--mod M). Exact needs all 2^n
+// digits, so n is bounded; modulo M takes n of any length.
+const EXACT_MAX_N: usize = 100_000;
+
+trait Count: Clone + fmt::Display {
+    fn zero(&self) -> Self;
+    fn one(&self) -> Self;
+    fn pow2(&self, n: &str) -> Self;  // 2^n, n decimal
+    fn add(&self, other: &Self) -> Self;
+    fn mul(&self, other: &Self) -> Self;
+}
+
+// unsigned, 32 bit limbs with the lowest first, no zero limbs at the end
+#[derive(Clone, PartialEq, Debug)]
+struct Big(Vec<u32>);
+
+impl Big {
+    fn trim(mut self) -> Big {
+        while self.0.last() == Some(&0) {
+            self.0.pop();
+        }
+        self
     }
+}
 
-    sum
+impl Count for Big {
+    fn zero(&self) -> Big { Big(Vec::new()) }
+    fn one(&self) -> Big { Big(vec![1]) }
+
+    fn pow2(&self, n: &str) -> Big {
+        let n = n.parse::<usize>().expect("exponent too large for an exact answer");
+        let mut limbs = vec![0; n/32 + 1];
+        limbs[n/32] = 1 << (n%32);
+        Big(limbs)
+    }
+
+    fn add(&self, other: &Big) -> Big {
+        let mut limbs: Vec<u32> = Vec::with_capacity(std::cmp::max(self.0.len(), other.0.len()) + 1);
+        let mut carry = 0u64;
+        for i in 0..std::cmp::max(self.0.len(), other.0.len()) {
+            let sum = *self.0.get(i).unwrap_or(&0) as u64 + *other.0.get(i).unwrap_or(&0) as u64 + carry;
+            limbs.push(sum as u32);
+            carry = sum >> 32;
+        }
+        limbs.push(carry as u32);
+        Big(limbs).trim()
+    }
+
+    fn mul(&self, other: &Big) -> Big {
+        let mut limbs = vec![0u32; self.0.len() + other.0.len()];
+        for (i, &a) in self.0.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &b) in other.0.iter().enumerate() {
+                let sum = a as u64 * b as u64 + limbs[i+j] as u64 + carry;
+                limbs[i+j] = sum as u32;
+                carry = sum >> 32;
+            }
+            limbs[i + other.0.len()] = carry as u32;
+        }
+        Big(limbs).trim()
+    }
 }
 
+impl fmt::Display for Big {
+    // base 10^9 chunks, taken off the lowest end
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut limbs = self.0.clone();
+        let mut chunks: Vec<u32> = Vec::new();
+        while ! limbs.is_empty() {
+            let mut rest = 0u64;
+            for limb in limbs.iter_mut().rev() {
+                let cur = (rest << 32) | *limb as u64;
+                *limb = (cur / 1_000_000_000) as u32;
+                rest = cur % 1_000_000_000;
+            }
+            chunks.push(rest as u32);
+            while limbs.last() == Some(&0) {
+                limbs.pop();
+            }
+        }
+        write!(f, "{}", chunks.pop().unwrap_or(0))?;
+        for chunk in chunks.iter().rev() {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Modular {
+    value: u64,  // below m
+    m: u64,
+}
+
+impl Count for Modular {
+    fn zero(&self) -> Modular { Modular { value: 0, m: self.m } }
+    fn one(&self) -> Modular { Modular { value: 1 % self.m, m: self.m } }
+
+    // digit by digit: 2^(10a+d) = (2^a)^10 * 2^d
+    fn pow2(&self, n: &str) -> Modular {
+        let mut r = self.one();
+        for d in n.bytes().map(|c| (c - b'0') as u32) {
+            let r2 = r.mul(&r);
+            let r4 = r2.mul(&r2);
+            let r8 = r4.mul(&r4);
+            r = r8.mul(&r2);
+            for _ in 0..d {
+                r = r.add(&Modular { value: r.value, m: self.m });
+            }
+        }
+        r
+    }
+
+    fn add(&self, other: &Modular) -> Modular {
+        Modular { value: ((self.value as u128 + other.value as u128) % self.m as u128) as u64, m: self.m }
+    }
+
+    fn mul(&self, other: &Modular) -> Modular {
+        Modular { value: ((self.value as u128 * other.value as u128) % self.m as u128) as u64, m: self.m }
+    }
+}
+
+impl fmt::Display for Modular {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+// n-1 of a positive decimal number
+fn decimal_pred(n: &str) -> String {
+    let mut digits: Vec<u8> = n.bytes().collect();
+    for d in digits.iter_mut().rev() {
+        if *d == b'0' {
+            *d = b'9';
+        } else {
+            *d -= 1;
+            break;
+        }
+    }
+    let text = String::from_utf8(digits).unwrap();
+    match text.trim_start_matches('0') {
+        "" => "0".to_string(),
+        rest => rest.to_string(),
+    }
+}
+
+/* ------------------------------------------------------------- Options */
+// Command line, all optional (CodinGame runs the program without any):
+//   --mod M         answer modulo M, n may then be of any size
+//   --simulate      unfold the sheet for real and count, see Simulation (small sheets and n)
+//   --draw          print the unfolded sheet instead, see Drawing
+//   --log LEVEL     quiet, summary, group or board, see Logging
+//   --log-events    log as parsable events
+struct Options {
+    modulus: Option<u64>,
+    simulate: bool,
+    draw: bool,
+    log: Option<String>,
+    log_events: bool,
+}
+
+impl Options {
+    fn from_args() -> Options {
+        let mut options = Options { modulus: None, simulate: false, draw: false, log: None, log_events: false };
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--mod" => {
+                    let m = args.next().and_then(|m| m.parse::<u64>().ok()).filter(|&m| m > 0);
+                    options.modulus = Some(m.expect("--mod needs a positive number"));
+                },
+                "--simulate" => options.simulate = true,
+                "--draw" => options.draw = true,
+                "--log" => options.log = Some(args.next().expect("--log needs a level")),
+                "--log-events" => options.log_events = true,
+                _ => panic!("unknown option {}", arg),
+            }
+        }
+        options
+    }
+}
+
+/* ------------------------------------------------------------- Logging */
+// Goes to stderr. Level from --log or CG_LOG (default summary):
+//   quiet    nothing
+//   summary  result
+//   group    pieces counted for every group
+//   board    and the sheet before and after grouping, and unfolded
+// --log-events or CG_LOG_FORMAT=events writes one "event=NAME key=value ..." line
+// per event instead, values quoted and escaped when needed, for tooling.
+#[derive(PartialEq,PartialOrd,Clone,Copy,Debug)]
+enum LogLevel {
+    Quiet,
+    Summary,
+    Group,
+    Board,
+}
 
+static LOG_LEVEL: AtomicUsize = AtomicUsize::new(LogLevel::Summary as usize);
+static LOG_EVENTS: AtomicBool = AtomicBool::new(false);
 
+impl LogLevel {
+    fn parse(name: &str) -> Option<LogLevel> {
+        match name {
+            "quiet" => Some(LogLevel::Quiet),
+            "summary" => Some(LogLevel::Summary),
+            "group" => Some(LogLevel::Group),
+            "board" => Some(LogLevel::Board),
+            _ => None,
+        }
+    }
+}
+
+// --log LEVEL and --log-events arguments win over environment
+fn log_init(level: Option<&str>, events: bool) {
+    let env_level = env::var("CG_LOG").ok();
+    if let Some(name) = level.or(env_level.as_deref()) {
+        let level = LogLevel::parse(name).expect("log level: quiet, summary, group or board");
+        LOG_LEVEL.store(level as usize, Ordering::Relaxed);
+    }
+    let env_events = env::var("CG_LOG_FORMAT").map(|f| f == "events").unwrap_or(false);
+    LOG_EVENTS.store(events || env_events, Ordering::Relaxed);
+}
+
+fn log_enabled(level: LogLevel) -> bool {
+    level != LogLevel::Quiet && level as usize <= LOG_LEVEL.load(Ordering::Relaxed)
+}
+
+fn log_line(event: &str, fields: &[(&str, String)], events: bool) -> String {
+    let mut line = if events { format!("event={}", event) } else { event.to_string() };
+    for &(key, ref value) in fields {
+        let plain = ! value.is_empty() && value.chars().all(|c| c.is_alphanumeric() || "_-.,@()".contains(c));
+        if ! events || plain {
+            line.push_str(&format!(" {}={}", key, value));
+        } else {
+            line.push_str(&format!(" {}={:?}", key, value));  // quoted, \n and " escaped
+        }
+    }
+    line
+}
+
+/* ------------------------------------------------------------- tests */
+#[test]
+fn board_size() {
+    // bigger than the old fixed 100x100 grid
+    let rows: Vec<String> = (0..150).map(|y| if y % 2 == 0 { "#".repeat(120) } else { ".".repeat(120) }).collect();
+    let mut board = Board::from_rows(120, 150, &rows).unwrap();
+    board.group();
+    assert_eq!(board.groups, 75);
+    assert_eq!(answer(&board.analysis(), "0", &Big(Vec::new())).to_string(), "75");
+
+    let rows = vec!["#.".to_string(), "#".to_string()];
+    assert!(Board::from_rows(2, 2, &rows).is_err());
+    assert!(Board::from_rows(2, 1, &rows[..1]).is_ok());
+    assert!(Board::from_rows(2, 1, &["#x".to_string()]).is_err());
+    assert!(Board::from_rows(2, 3, &rows).is_err());
+}
+
+#[test]
+fn big_answers() {
+    let rows: Vec<String> = vec!["#..".to_string(), "...".to_string(), "...".to_string()];
+    let mut board = Board::from_rows(3, 3, &rows).unwrap();
+    board.group();
+    let edges = board.analysis();  // top left corner: U(n)*U(n) = 4^(n-1)
+    assert_eq!(answer(&edges, "40", &Big(Vec::new())).to_string(), "302231454903657293676544");
+    assert_eq!(answer(&edges, "40", &Modular { value: 0, m: 1_000_000_007 }).value, (302231454903657293676544u128 % 1_000_000_007) as u64);
+
+    // exact and modular agree
+    let decimal_mod = |text: &str, m: u64| text.bytes().fold(0u64, |r, c| (r * 10 + (c - b'0') as u64) % m);
+    for &n in ["0", "1", "2", "31", "32", "33", "100", "1000"].iter() {
+        let exact = answer(&edges, n, &Big(Vec::new())).add(&W(n, &Big(Vec::new())).mul(&V(n, &Big(Vec::new()))));
+        let modular = answer(&edges, n, &Modular { value: 0, m: 998_244_353 }).add(&W(n, &Modular { value: 0, m: 998_244_353 }).mul(&V(n, &Modular { value: 0, m: 998_244_353 })));
+        assert_eq!(decimal_mod(&exact.to_string(), 998_244_353), modular.value, "n={}", n);
+    }
+
+    // 2^(10^30) mod 7: 10^30 = 1 mod 3, so 2
+    assert_eq!(Modular { value: 0, m: 7 }.pow2(&format!("1{}", "0".repeat(30))).value, 2);
+    assert_eq!(Modular { value: 0, m: 1 }.pow2("5").value, 0);
+    assert_eq!((decimal_pred("1000"), decimal_pred("1")), ("999".to_string(), "0".to_string()));
+    assert_eq!(Big(Vec::new()).to_string(), "0");
+}
+
+#[cfg(test)]
+struct Rng(u64);
+
+#[cfg(test)]
+impl Rng {
+    // xorshift64*
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545f4914f6cdd1d) % n as u64) as usize
+    }
+}
+
+#[test]
+fn unfold_mirrors() {
+    let rows: Vec<String> = vec!["#..".to_string(), "..#".to_string()];
+    let board = Board::from_rows(3, 2, &rows).unwrap().unfold();
+    assert_eq!(board.to_string(), "#....#\n..##..\n..##..\n#....#\n");
+}
+
+#[test]
+fn draw_unfolded() {
+    let rows: Vec<String> = vec!["#.".to_string(), ".#".to_string()];
+    let mut board = Board::from_rows(2, 2, &rows).unwrap();
+    board.group();
+    assert_eq!(board.draw(1).unwrap(), "n=1\nb..b 0..1 1\n.aa. .22.\n.aa. .22. 0\nb..b 3..4\n");
+    assert_eq!(board.draw(0).unwrap(), "n=0\na. 0. 0\n.b .1\n");
+    assert!(board.draw(12).is_none());
+    assert_eq!((label(0, 1), label(51, 1), label(52, 2), label(275, 2)), ("a".to_string(), "Z".to_string(), "ba".to_string(), "fp".to_string()));
+    assert!(board.draw(1 << 31).is_none());
+    assert!(board.simulate(u32::MAX).is_none());
+}
+
+#[test]
+fn random_unfolding() {
+    // answer() against the simulation, on random sheets
+    let seed = env::var("CG_TEST_SEED").ok().and_then(|s| s.parse::<u64>().ok()).unwrap_or(2018);
+    let mut rng = Rng(seed.wrapping_mul(0x9e3779b97f4a7c15) | 1);
+    for i in 0..300 {
+        let (w, h, n) = (1 + rng.below(6), 1 + rng.below(6), rng.below(4) as u32);
+        let density = 1 + rng.below(9);
+        let rows: Vec<String> = (0..h).map(|_| (0..w).map(|_| if rng.below(10) < density { '#' } else { '.' }).collect()).collect();
+        let mut board = Board::from_rows(w, h, &rows).unwrap();
+        let simulated = board.simulate(n).unwrap();
+        board.group();
+        let counted = answer(&board.analysis(), &n.to_string(), &Big(Vec::new())).to_string();
+        assert_eq!(counted, simulated.to_string(), "seed {}, case {}, n={}:\n{}", seed, i, n, rows.join("\n"));
+    }
+}